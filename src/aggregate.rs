@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeDelta, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, json};
+
+use crate::items::Sample;
+
+/// Width of the buckets samples are grouped into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    Minute,
+    FiveMinutes,
+    Hour,
+    #[default]
+    Day,
+}
+
+impl Interval {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "minute" => Some(Interval::Minute),
+            "five_minutes" => Some(Interval::FiveMinutes),
+            "hour" => Some(Interval::Hour),
+            "day" => Some(Interval::Day),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Interval::Minute => "minute",
+            Interval::FiveMinutes => "five_minutes",
+            Interval::Hour => "hour",
+            Interval::Day => "day",
+        }
+    }
+
+    pub fn duration(self) -> TimeDelta {
+        match self {
+            Interval::Minute => TimeDelta::minutes(1),
+            Interval::FiveMinutes => TimeDelta::minutes(5),
+            Interval::Hour => TimeDelta::hours(1),
+            Interval::Day => TimeDelta::days(1),
+        }
+    }
+
+    /// Start of the bucket containing `at`, in local time at `offset`.
+    fn bucket_start(self, at: DateTime<FixedOffset>, offset: FixedOffset) -> NaiveDateTime {
+        let local = at.with_timezone(&offset).naive_local();
+        let (hour, minute) = match self {
+            Interval::Minute => (local.hour(), local.minute()),
+            Interval::FiveMinutes => (local.hour(), local.minute() / 5 * 5),
+            Interval::Hour => (local.hour(), 0),
+            Interval::Day => (0, 0),
+        };
+        local
+            .date()
+            .and_hms_opt(hour, minute, 0)
+            .expect("valid bucket start")
+    }
+}
+
+/// Running min/max/sum/count of a set of values.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Stats {
+    pub fn new(value: f64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    /// Stats of a sample: those of the raw samples for a downsampled item, otherwise
+    /// of its single value.
+    pub fn of(sample: &Sample) -> Self {
+        sample.stats.unwrap_or_else(|| Self::new(sample.value))
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    pub fn avg(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+/// Column-oriented series, one entry per non-empty bucket in time order.
+#[derive(Debug, Default, Serialize)]
+pub struct Series {
+    /// Unit of the first sample; values are aggregated as stored.
+    pub unit: Option<String>,
+    pub start: Vec<DateTime<FixedOffset>>,
+    pub min: Vec<f64>,
+    pub max: Vec<f64>,
+    pub avg: Vec<f64>,
+    pub sum: Vec<f64>,
+    pub count: Vec<u64>,
+}
+
+/// Groups samples into buckets of one interval.
+pub struct Aggregator {
+    interval: Interval,
+    offset: FixedOffset,
+    unit: Option<String>,
+    buckets: BTreeMap<NaiveDateTime, Stats>,
+}
+
+impl Aggregator {
+    pub fn new(interval: Interval, offset: FixedOffset) -> Self {
+        Self {
+            interval,
+            offset,
+            unit: None,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, sample: &Sample) {
+        if self.unit.is_none() {
+            self.unit.clone_from(&sample.unit);
+        }
+        let start = self.interval.bucket_start(sample.start, self.offset);
+        let stats = Stats::of(sample);
+        self.buckets
+            .entry(start)
+            .and_modify(|bucket| bucket.merge(&stats))
+            .or_insert(stats);
+    }
+
+    /// Unit of the first sample added.
+    pub fn unit(&self) -> Option<&str> {
+        self.unit.as_deref()
+    }
+
+    /// Non-empty buckets in time order, with their start.
+    pub fn buckets(&self) -> impl Iterator<Item = (DateTime<FixedOffset>, Stats)> + '_ {
+        self.buckets.iter().filter_map(|(start, stats)| {
+            Some((self.offset.from_local_datetime(start).single()?, *stats))
+        })
+    }
+
+    pub fn finish(self) -> Series {
+        let mut series = Series {
+            unit: self.unit.clone(),
+            ..Series::default()
+        };
+        for (start, stats) in self.buckets() {
+            series.start.push(start);
+            series.min.push(stats.min);
+            series.max.push(stats.max);
+            series.avg.push(stats.avg());
+            series.sum.push(stats.sum);
+            series.count.push(stats.count);
+        }
+        series
+    }
+}
+
+/// Field marking stored items that hold aggregates rather than raw samples; its value
+/// is the interval name.
+pub const DOWNSAMPLED_FIELD: &str = "downsampled";
+
+/// Flat stored items, one per bucket, standing in for the raw samples of `metric`.
+/// They read back as samples whose value is the bucket average and whose stats are the
+/// bucket's, so aggregating them again gives the same result as the raw samples.
+pub fn downsampled_items<'a>(
+    metric: &str,
+    interval: Interval,
+    aggregator: &'a Aggregator,
+) -> impl Iterator<Item = JsonValue> + use<'a> {
+    let metric = metric.to_string();
+    aggregator.buckets().map(move |(start, stats)| {
+        json!({
+            "type": metric,
+            "unit": aggregator.unit(),
+            "start": start.to_rfc3339(),
+            "end": (start + interval.duration()).to_rfc3339(),
+            "value": stats.avg(),
+            "min": stats.min,
+            "max": stats.max,
+            "sum": stats.sum,
+            "count": stats.count,
+            DOWNSAMPLED_FIELD: interval.name(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(start: &str, value: f64) -> Sample {
+        Sample {
+            metric: "heart_rate".into(),
+            value,
+            unit: Some("count/min".into()),
+            start: DateTime::parse_from_rfc3339(start).unwrap(),
+            end: None,
+            source: None,
+            stats: None,
+        }
+    }
+
+    #[test]
+    fn daily_buckets_follow_the_offset() {
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        let mut aggregator = Aggregator::new(Interval::Day, offset);
+        aggregator.add(&sample("2024-03-01T21:30:00Z", 60.0));
+        aggregator.add(&sample("2024-03-01T23:30:00Z", 80.0));
+        aggregator.add(&sample("2024-03-02T08:00:00Z", 70.0));
+        let series = aggregator.finish();
+
+        assert_eq!(series.unit.as_deref(), Some("count/min"));
+        assert_eq!(
+            series.start,
+            [
+                DateTime::parse_from_rfc3339("2024-03-01T00:00:00+02:00").unwrap(),
+                DateTime::parse_from_rfc3339("2024-03-02T00:00:00+02:00").unwrap(),
+            ]
+        );
+        assert_eq!(series.count, [1, 2]);
+        assert_eq!(series.min, [60.0, 70.0]);
+        assert_eq!(series.max, [60.0, 80.0]);
+        assert_eq!(series.sum, [60.0, 150.0]);
+        assert_eq!(series.avg, [60.0, 75.0]);
+    }
+
+    #[test]
+    fn hourly_buckets_split_within_a_day() {
+        let mut aggregator = Aggregator::new(Interval::Hour, FixedOffset::east_opt(0).unwrap());
+        aggregator.add(&sample("2024-03-01T10:59:59Z", 1.0));
+        aggregator.add(&sample("2024-03-01T11:00:00Z", 2.0));
+        aggregator.add(&sample("2024-03-01T11:30:00Z", 3.0));
+        let series = aggregator.finish();
+
+        assert_eq!(series.count, [1, 2]);
+        assert_eq!(series.start[1].to_rfc3339(), "2024-03-01T11:00:00+00:00");
+    }
+
+    #[test]
+    fn downsampled_items_aggregate_like_their_raw_samples() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let raw = [
+            sample("2024-03-01T10:05:00Z", 50.0),
+            sample("2024-03-01T10:35:00Z", 90.0),
+            sample("2024-03-01T11:15:00Z", 70.0),
+        ];
+        let mut hourly = Aggregator::new(Interval::Hour, utc);
+        let mut direct = Aggregator::new(Interval::Day, utc);
+        for s in &raw {
+            hourly.add(s);
+            direct.add(s);
+        }
+        let items: Vec<JsonValue> =
+            downsampled_items("heart_rate", Interval::Hour, &hourly).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["end"], "2024-03-01T11:00:00+00:00");
+
+        let mut from_items = Aggregator::new(Interval::Day, utc);
+        for s in items.iter().flat_map(crate::items::samples) {
+            from_items.add(&s);
+        }
+        let (a, b) = (from_items.finish(), direct.finish());
+        assert_eq!(
+            (a.min, a.max, a.sum, a.count),
+            (b.min, b.max, b.sum, b.count)
+        );
+        assert_eq!(a.avg, [70.0]);
+    }
+
+    #[test]
+    fn empty_aggregation_has_no_buckets() {
+        let series = Aggregator::new(Interval::Day, FixedOffset::east_opt(0).unwrap()).finish();
+        assert!(series.start.is_empty());
+        assert_eq!(series.unit, None);
+    }
+}
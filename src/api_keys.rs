@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::scopes::Scopes;
+
+// Prefix of generated tokens, so leaked keys are easy to spot in logs and scanners.
+const TOKEN_PREFIX: &str = "ahe_";
+const TOKEN_BYTES: usize = 32;
+
+/// Owner and device restrictions of one API key.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub user: String,
+    /// `None` allows every device; otherwise only these `device_name` values.
+    pub devices: Option<Arc<[String]>>,
+    pub scopes: Scopes,
+}
+
+/// API keys keyed by the hex SHA-256 digest of the token; tokens are never stored.
+#[derive(Debug, Default)]
+pub struct ApiKeyStore {
+    keys: HashMap<String, ApiKey>,
+}
+
+impl ApiKeyStore {
+    /// Load an API keys file with one `username:sha256hex[:device,...[:scope,...]]`
+    /// entry per line; an empty device list allows every device and scopes default
+    /// to `ingest`. Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut store = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                |msg: &str| Error::Config(format!("{}:{}: {msg}", path.display(), idx + 1));
+            let mut fields = line.splitn(4, ':');
+            let (Some(user), Some(digest)) = (fields.next(), fields.next()) else {
+                return Err(invalid("expected username:sha256[:devices[:scopes]]"));
+            };
+            if user.is_empty() {
+                return Err(invalid("expected username:sha256[:devices[:scopes]]"));
+            }
+            let digest = digest.to_ascii_lowercase();
+            if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid("key hash must be 64 hex characters (SHA-256)"));
+            }
+            let devices = fields
+                .next()
+                .map(|list| {
+                    list.split(',')
+                        .map(str::trim)
+                        .filter(|d| !d.is_empty())
+                        .map(str::to_string)
+                        .collect::<Arc<[String]>>()
+                })
+                .filter(|devices| !devices.is_empty());
+            let scopes = match fields.next() {
+                Some(list) => Scopes::parse_list(list)
+                    .ok_or_else(|| invalid("scopes must be a list of ingest, read, admin"))?,
+                None => Scopes::INGEST,
+            };
+            store.keys.insert(
+                digest,
+                ApiKey {
+                    user: user.to_string(),
+                    devices,
+                    scopes,
+                },
+            );
+        }
+        debug!(path = %path.display(), keys = store.keys.len(), "API keys file loaded");
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn lookup(&self, token: &str) -> Option<&ApiKey> {
+        self.keys.get(&digest(token))
+    }
+}
+
+/// Hex SHA-256 of a token, as stored in the API keys file.
+pub fn digest(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a new random token.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{TOKEN_PREFIX}{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::scopes::Scope;
+
+    fn load(text: &str) -> Result<ApiKeyStore> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        ApiKeyStore::from_file(file.path())
+    }
+
+    #[test]
+    fn loads_devices_and_scopes() {
+        let text = format!(
+            "# keys\nalice:{}\nbob:{}: phone ,watch:read\ncarol:{}::admin\n",
+            digest("a"),
+            digest("b").to_ascii_uppercase(),
+            digest("c"),
+        );
+        let store = load(&text).unwrap();
+        assert_eq!(store.len(), 3);
+
+        let alice = store.lookup("a").unwrap();
+        assert_eq!(alice.user, "alice");
+        assert!(alice.devices.is_none());
+        assert_eq!(alice.scopes, Scopes::INGEST);
+
+        let bob = store.lookup("b").unwrap();
+        assert_eq!(bob.devices.as_deref().unwrap(), ["phone", "watch"]);
+        assert!(bob.scopes.allows(Scope::Read) && !bob.scopes.allows(Scope::Ingest));
+
+        let carol = store.lookup("c").unwrap();
+        assert!(carol.devices.is_none());
+        assert!(carol.scopes.allows(Scope::Admin));
+
+        assert!(store.lookup("d").is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(load("alice\n").is_err());
+        assert!(load(&format!(":{}\n", digest("a"))).is_err());
+        assert!(load("alice:abc123\n").is_err());
+        assert!(load(&format!("alice:{}::owner\n", digest("a"))).is_err());
+    }
+
+    #[test]
+    fn generated_tokens_are_prefixed_and_random() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 2 * TOKEN_BYTES);
+        assert_ne!(token, generate_token());
+    }
+}
@@ -0,0 +1,698 @@
+use axum::body::Body;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::StreamExt;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue, json};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::Mutex;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{debug, instrument, warn};
+
+use crate::error::{Error, Result};
+use crate::gpx;
+use crate::metrics;
+use crate::s3::{self, for_storage, s3_key_for_device_date};
+use crate::state::AppState;
+
+// Top-level elements of export.xml that are converted into JSON items.
+const IMPORTED_ELEMENTS: &[&str] = &["Record", "Workout", "ActivitySummary", "Correlation"];
+
+// Number of parsed items buffered before they are flushed to S3.
+const FLUSH_ITEMS: usize = 10_000;
+
+// Device segment used for items without a source (e.g. ActivitySummary).
+const DEFAULT_DEVICE: &str = "apple-health";
+
+// Attempts at merging into a day file that keeps changing under the import.
+const MERGE_ATTEMPTS: usize = 5;
+
+// Finished imports whose status is kept for `GET /import/apple/{id}`.
+const KEPT_JOBS: usize = 100;
+
+// Timestamp format used throughout export.xml, e.g. "2024-01-01 08:00:00 +0100".
+pub const APPLE_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Authenticated user whose prefix receives the data.
+    pub user: Option<String>,
+    /// Store every item under this device instead of the record's `sourceName`.
+    pub device_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportSummary {
+    pub items: usize,
+    pub skipped: usize,
+    /// Items left out because their source name falls in the reserved `_` namespace.
+    pub reserved: usize,
+    pub routes: usize,
+    pub writes: usize,
+    /// Items left out because the day file already held them.
+    pub duplicates: usize,
+}
+
+/// State of an import started by `POST /import/apple`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ImportStatus {
+    Running,
+    Finished(ImportSummary),
+    Failed { error: String },
+}
+
+/// Status of recent imports by job id, kept in memory until the process exits. Each
+/// job is visible only to the user who started it.
+#[derive(Debug, Default)]
+pub struct ImportJobs {
+    jobs: Mutex<VecDeque<ImportJob>>,
+}
+
+#[derive(Debug)]
+struct ImportJob {
+    id: String,
+    owner: Option<String>,
+    status: ImportStatus,
+}
+
+impl ImportJobs {
+    /// Register a running import started by `owner` and return its job id.
+    pub fn start(&self, owner: Option<&str>) -> String {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let mut jobs = self.jobs.lock().expect("import jobs lock");
+        jobs.push_back(ImportJob {
+            id: id.clone(),
+            owner: owner.map(str::to_string),
+            status: ImportStatus::Running,
+        });
+        if jobs.len() > KEPT_JOBS
+            && let Some(oldest) = jobs
+                .iter()
+                .position(|job| !matches!(job.status, ImportStatus::Running))
+        {
+            jobs.remove(oldest);
+        }
+        id
+    }
+
+    pub fn finish(&self, id: &str, status: ImportStatus) {
+        let mut jobs = self.jobs.lock().expect("import jobs lock");
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            job.status = status;
+        }
+    }
+
+    pub fn get(&self, id: &str, owner: Option<&str>) -> Option<ImportStatus> {
+        let jobs = self.jobs.lock().expect("import jobs lock");
+        jobs.iter()
+            .find(|job| job.id == id && job.owner.as_deref() == owner)
+            .map(|job| job.status.clone())
+    }
+}
+
+#[derive(Debug, Default)]
+struct ParseSummary {
+    items: usize,
+    skipped: usize,
+    reserved: usize,
+    routes: usize,
+}
+
+// GPX files of `workout-routes/`, indexed by file name.
+struct RouteIndex {
+    archive: zip::ZipArchive<File>,
+    entries: HashMap<String, String>,
+}
+
+// Items grouped by (device, UTC day), ready to be merged into day files.
+type Batch = Vec<((String, NaiveDate), Vec<JsonValue>)>;
+
+/// Write an uploaded request body to a temporary file so it can be read as a zip,
+/// failing with [`Error::UploadTooLarge`] once it exceeds `max_bytes`.
+pub async fn spool_upload(body: Body, max_bytes: u64) -> Result<NamedTempFile> {
+    let tmp = NamedTempFile::new()?;
+    let mut file = tokio::fs::File::from_std(tmp.reopen()?);
+    let mut stream = body.into_data_stream();
+    let mut bytes = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        bytes += chunk.len() as u64;
+        if bytes > max_bytes {
+            return Err(Error::UploadTooLarge(max_bytes));
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    debug!(bytes, path = %tmp.path().display(), "upload spooled to disk");
+    Ok(tmp)
+}
+
+/// Import an Apple Health export (zip archive or bare export.xml) into day files.
+///
+/// The XML is parsed on a blocking thread and handed over in bounded batches, so
+/// memory use does not grow with the size of the export.
+#[instrument(skip(state))]
+pub async fn import_file(
+    state: &AppState,
+    path: &Path,
+    opts: ImportOptions,
+) -> Result<ImportSummary> {
+    let (tx, mut rx) = mpsc::channel::<Batch>(1);
+    let user = opts.user.clone();
+    let path = path.to_path_buf();
+    let parser = tokio::task::spawn_blocking(move || parse_file(&path, &opts, &tx));
+
+    let (mut writes, mut duplicates) = (0, 0);
+    while let Some(batch) = rx.recv().await {
+        for ((device, date), items) in batch {
+            let key = s3_key_for_device_date(&state.prefix, user.as_deref(), &device, date);
+            let count = items.len();
+            debug!(%key, items = count, "merging imported items");
+            let payload = for_storage(state, &device, JsonValue::Array(items));
+            let skipped = merge_new_items(state, &key, payload).await?;
+            metrics::add_imported_items((count - skipped.min(count)) as u64);
+            duplicates += skipped;
+            writes += 1;
+        }
+    }
+
+    let parsed = parser.await??;
+    Ok(ImportSummary {
+        items: parsed.items,
+        skipped: parsed.skipped,
+        reserved: parsed.reserved,
+        routes: parsed.routes,
+        writes,
+        duplicates,
+    })
+}
+
+// Merge imported items into a day file, leaving out the items it already holds so a
+// re-imported export adds only what is new. The day file is written back only if no
+// ingest changed it since it was read, otherwise the merge starts over. Returns the
+// number of items left out.
+async fn merge_new_items(state: &AppState, key: &str, payload: JsonValue) -> Result<usize> {
+    let items = match payload {
+        JsonValue::Array(items) => items,
+        other => vec![other],
+    };
+    for attempt in 1..=MERGE_ATTEMPTS {
+        let (existing, etag) = s3::load_json_versioned(state, key).await?.unzip();
+        let mut seen: HashSet<String> = match &existing {
+            Some(JsonValue::Array(stored)) => stored.iter().filter_map(import_identity).collect(),
+            Some(item) => import_identity(item).into_iter().collect(),
+            None => HashSet::new(),
+        };
+        let fresh: Vec<JsonValue> = items
+            .iter()
+            .filter(|item| import_identity(item).is_none_or(|id| seen.insert(id)))
+            .cloned()
+            .collect();
+        let duplicates = items.len() - fresh.len();
+        if duplicates > 0 {
+            debug!(%key, duplicates, "skipping items already imported");
+        }
+        if fresh.is_empty() {
+            return Ok(duplicates);
+        }
+        let merged = match existing {
+            Some(old) => s3::merge_json(old, JsonValue::Array(fresh)),
+            None => JsonValue::Array(fresh),
+        };
+        if s3::put_json_if_unchanged(state, key, &merged, etag.as_deref()).await? {
+            return Ok(duplicates);
+        }
+        debug!(%key, attempt, "day file changed during the import merge, retrying");
+    }
+    Err(Error::Import(format!(
+        "{key} kept changing during the import"
+    )))
+}
+
+// What makes an imported item the same across imports: the data point uuid in the
+// Open mHealth format (derived from its body), otherwise the element's kind, type,
+// dates and source. Items without any date are never treated as duplicates.
+fn import_identity(item: &JsonValue) -> Option<String> {
+    if let Some(uuid) = item.pointer("/header/uuid").and_then(JsonValue::as_str) {
+        return Some(uuid.to_string());
+    }
+    let obj = item.as_object()?;
+    let field = |name: &str| {
+        obj.get(name)
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default()
+    };
+    if field("startDate").is_empty() && field("dateComponents").is_empty() {
+        return None;
+    }
+    Some(
+        [
+            "kind",
+            "type",
+            "startDate",
+            "endDate",
+            "sourceName",
+            "dateComponents",
+        ]
+        .map(field)
+        .join("\u{1f}"),
+    )
+}
+
+fn parse_file(path: &Path, opts: &ImportOptions, tx: &mpsc::Sender<Batch>) -> Result<ParseSummary> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let is_zip = file.read(&mut magic)? == magic.len() && &magic == b"PK\x03\x04";
+    file.rewind()?;
+
+    if !is_zip {
+        debug!("parsing bare export.xml");
+        return parse_export_xml(BufReader::new(file), None, opts, tx);
+    }
+
+    let mut archive = zip::ZipArchive::new(file)?;
+    let name = archive
+        .file_names()
+        .find(|n| is_export_xml(n))
+        .map(str::to_string)
+        .ok_or_else(|| Error::Import("export.xml not found in archive".to_string()))?;
+    // Routes are read through a second handle while export.xml is being streamed.
+    let mut routes = RouteIndex::open(path)?;
+    debug!(entry = %name, routes = routes.entries.len(), "parsing export.xml from archive");
+    let entry = archive.by_name(&name)?;
+    parse_export_xml(BufReader::new(entry), Some(&mut routes), opts, tx)
+}
+
+fn is_export_xml(name: &str) -> bool {
+    name.rsplit('/').next() == Some("export.xml")
+}
+
+impl RouteIndex {
+    fn open(path: &Path) -> Result<Self> {
+        let archive = zip::ZipArchive::new(File::open(path)?)?;
+        let entries = archive
+            .file_names()
+            .filter(|n| n.contains("workout-routes/") && n.ends_with(".gpx"))
+            .filter_map(|n| Some((file_name(n)?.to_string(), n.to_string())))
+            .collect();
+        Ok(Self { archive, entries })
+    }
+
+    // Convert the GPX file referenced by a `FileReference` path into GeoJSON.
+    fn feature(&mut self, path: &str) -> Result<Option<JsonValue>> {
+        let Some(entry) = file_name(path).and_then(|n| self.entries.get(n)) else {
+            return Ok(None);
+        };
+        let file = self.archive.by_name(entry)?;
+        gpx::to_feature(BufReader::new(file), path).map(Some)
+    }
+}
+
+fn file_name(path: &str) -> Option<&str> {
+    path.rsplit('/').next().filter(|n| !n.is_empty())
+}
+
+// Embed the routes of a Workout as a GeoJSON FeatureCollection under `route`.
+fn attach_routes(obj: &mut Map<String, JsonValue>, routes: &mut RouteIndex) -> usize {
+    let paths: Vec<String> = obj
+        .get("WorkoutRoute")
+        .and_then(JsonValue::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|route| route.get("FileReference")?.as_array())
+        .flatten()
+        .filter_map(|file| file.get("path")?.as_str().map(str::to_string))
+        .collect();
+
+    let mut features = Vec::with_capacity(paths.len());
+    for path in paths {
+        match routes.feature(&path) {
+            Ok(Some(feature)) => features.push(feature),
+            Ok(None) => debug!(%path, "workout route not found in archive"),
+            Err(err) => warn!(error = ?err, %path, "failed to read workout route"),
+        }
+    }
+    let count = features.len();
+    if count > 0 {
+        obj.insert(
+            "route".to_string(),
+            json!({ "type": "FeatureCollection", "features": features }),
+        );
+    }
+    count
+}
+
+fn parse_export_xml<R: BufRead>(
+    input: R,
+    mut routes: Option<&mut RouteIndex>,
+    opts: &ImportOptions,
+    tx: &mpsc::Sender<Batch>,
+) -> Result<ParseSummary> {
+    let mut reader = Reader::from_reader(input);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    // Element currently being built, with its open descendants.
+    let mut stack: Vec<(String, Map<String, JsonValue>)> = Vec::new();
+    // Depth of the last opened element; children of <HealthData> are at depth 2.
+    let mut depth = 0usize;
+    let mut pending: HashMap<(String, NaiveDate), Vec<JsonValue>> = HashMap::new();
+    let mut pending_items = 0usize;
+    let mut summary = ParseSummary::default();
+
+    loop {
+        let finished = match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                depth += 1;
+                let name = element_name(&e);
+                if !stack.is_empty() || (depth == 2 && is_imported(&name)) {
+                    stack.push((name, attributes(&e)?));
+                }
+                None
+            }
+            Event::Empty(e) => {
+                let name = element_name(&e);
+                let attrs = attributes(&e)?;
+                match stack.last_mut() {
+                    Some((_, parent)) => {
+                        attach_child(parent, &name, attrs);
+                        None
+                    }
+                    None if depth + 1 == 2 && is_imported(&name) => Some((name, attrs)),
+                    None => None,
+                }
+            }
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                match stack.pop() {
+                    Some((name, obj)) => match stack.last_mut() {
+                        Some((_, parent)) => {
+                            attach_child(parent, &name, obj);
+                            None
+                        }
+                        None => Some((name, obj)),
+                    },
+                    None => None,
+                }
+            }
+            Event::Eof => break,
+            _ => None,
+        };
+        buf.clear();
+
+        let Some((kind, mut obj)) = finished else {
+            continue;
+        };
+        if kind == "Workout"
+            && let Some(routes) = routes.as_deref_mut()
+        {
+            summary.routes += attach_routes(&mut obj, routes);
+        }
+        obj.insert("kind".to_string(), JsonValue::String(kind));
+        match group_for(&obj, opts) {
+            // Source names in the reserved `_` namespace would write into internal trees.
+            Some((device, _)) if s3::is_reserved_segment(&device) => {
+                debug!(%device, "skipping element from a reserved source");
+                summary.reserved += 1;
+            }
+            Some(group) => {
+                pending
+                    .entry(group)
+                    .or_default()
+                    .push(JsonValue::Object(obj));
+                pending_items += 1;
+                summary.items += 1;
+            }
+            None => {
+                debug!(?obj, "skipping element without a usable date");
+                summary.skipped += 1;
+            }
+        }
+
+        if pending_items >= FLUSH_ITEMS {
+            flush(&mut pending, tx)?;
+            pending_items = 0;
+        }
+    }
+
+    if !pending.is_empty() {
+        flush(&mut pending, tx)?;
+    }
+    Ok(summary)
+}
+
+fn flush(
+    pending: &mut HashMap<(String, NaiveDate), Vec<JsonValue>>,
+    tx: &mpsc::Sender<Batch>,
+) -> Result<()> {
+    tx.blocking_send(pending.drain().collect())
+        .map_err(|_| Error::Import("import writer stopped".to_string()))
+}
+
+fn is_imported(name: &str) -> bool {
+    IMPORTED_ELEMENTS.contains(&name)
+}
+
+fn element_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.name().as_ref()).into_owned()
+}
+
+fn attributes(e: &BytesStart) -> Result<Map<String, JsonValue>> {
+    let mut map = Map::new();
+    for attr in e.attributes() {
+        let attr = attr?;
+        let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+        let value = match attr.unescape_value() {
+            Ok(v) => v.into_owned(),
+            Err(_) => String::from_utf8_lossy(&attr.value).into_owned(),
+        };
+        // Numeric sample values become JSON numbers; everything else stays verbatim.
+        let value = match value.parse::<f64>() {
+            Ok(n) if key == "value" && n.is_finite() => JsonValue::from(n),
+            _ => JsonValue::String(value),
+        };
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+fn attach_child(
+    parent: &mut Map<String, JsonValue>,
+    name: &str,
+    mut child: Map<String, JsonValue>,
+) {
+    if name == "MetadataEntry" {
+        if let (Some(JsonValue::String(key)), Some(value)) =
+            (child.remove("key"), child.remove("value"))
+            && let JsonValue::Object(meta) = parent
+                .entry("metadata")
+                .or_insert_with(|| JsonValue::Object(Map::new()))
+        {
+            meta.insert(key, value);
+        }
+        return;
+    }
+    if let JsonValue::Array(children) = parent
+        .entry(name)
+        .or_insert_with(|| JsonValue::Array(Vec::new()))
+    {
+        children.push(JsonValue::Object(child));
+    }
+}
+
+// Pick the (device, UTC day) day file an element belongs to.
+fn group_for(obj: &Map<String, JsonValue>, opts: &ImportOptions) -> Option<(String, NaiveDate)> {
+    let date = match obj.get("dateComponents").and_then(JsonValue::as_str) {
+        Some(day) => NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?,
+        None => {
+            let start = obj.get("startDate").and_then(JsonValue::as_str)?;
+            DateTime::parse_from_str(start, APPLE_DATE_FORMAT)
+                .ok()?
+                .with_timezone(&Utc)
+                .date_naive()
+        }
+    };
+    let device = opts
+        .device_name
+        .clone()
+        .or_else(|| {
+            obj.get("sourceName")
+                .and_then(JsonValue::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| DEFAULT_DEVICE.to_string());
+    Some((device, date))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<HealthData locale="en_US">
+ <ExportDate value="2026-09-02 10:00:00 +0200"/>
+ <Me HKCharacteristicTypeIdentifierBiologicalSex="HKBiologicalSexNotSet"/>
+ <Record type="HKQuantityTypeIdentifierHeartRate" sourceName="Watch" unit="count/min" startDate="2026-09-01 08:00:00 +0200" endDate="2026-09-01 08:00:00 +0200" value="61">
+  <MetadataEntry key="HKMetadataKeyHeartRateMotionContext" value="1"/>
+ </Record>
+ <Record type="HKQuantityTypeIdentifierStepCount" sourceName="Phone" unit="count" startDate="2026-09-01 01:30:00 +0200" endDate="2026-09-01 01:40:00 +0200" value="120"/>
+ <Record type="HKQuantityTypeIdentifierStepCount" sourceName="Phone" unit="count" startDate="yesterday" value="1"/>
+ <Record type="HKQuantityTypeIdentifierStepCount" sourceName="_audit" unit="count" startDate="2026-09-01 09:00:00 +0000" value="2"/>
+ <Correlation type="HKCorrelationTypeIdentifierBloodPressure" sourceName="Cuff" startDate="2026-09-01 12:00:00 +0000" endDate="2026-09-01 12:00:00 +0000">
+  <Record type="HKQuantityTypeIdentifierBloodPressureSystolic" sourceName="Cuff" unit="mmHg" startDate="2026-09-01 12:00:00 +0000" endDate="2026-09-01 12:00:00 +0000" value="120"/>
+ </Correlation>
+ <ActivitySummary dateComponents="2026-09-01" activeEnergyBurned="300"/>
+</HealthData>
+"#;
+
+    type Groups = HashMap<(String, NaiveDate), Vec<JsonValue>>;
+
+    fn parse(opts: &ImportOptions) -> (ParseSummary, Groups) {
+        let (tx, mut rx) = mpsc::channel(8);
+        let summary = parse_export_xml(EXPORT.as_bytes(), None, opts, &tx).unwrap();
+        drop(tx);
+        let mut groups = HashMap::new();
+        while let Ok(batch) = rx.try_recv() {
+            groups.extend(batch);
+        }
+        (summary, groups)
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn groups_elements_by_source_and_utc_day() {
+        let (summary, groups) = parse(&ImportOptions::default());
+        assert_eq!(
+            (summary.items, summary.skipped, summary.reserved),
+            (4, 1, 1)
+        );
+        assert!(!groups.keys().any(|(device, _)| device.starts_with('_')));
+
+        let watch = &groups[&("Watch".to_string(), day("2026-09-01"))];
+        assert_eq!(watch[0]["kind"], "Record");
+        assert_eq!(watch[0]["value"], 61.0);
+        assert_eq!(
+            watch[0]["metadata"]["HKMetadataKeyHeartRateMotionContext"],
+            1.0
+        );
+
+        // 01:30 +0200 is still the previous UTC day.
+        assert!(groups.contains_key(&("Phone".to_string(), day("2026-08-31"))));
+
+        let cuff = &groups[&("Cuff".to_string(), day("2026-09-01"))];
+        assert_eq!(cuff[0]["kind"], "Correlation");
+        assert_eq!(cuff[0]["Record"][0]["value"], 120.0);
+
+        assert!(groups.contains_key(&(DEFAULT_DEVICE.to_string(), day("2026-09-01"))));
+    }
+
+    #[test]
+    fn device_name_overrides_the_source() {
+        let opts = ImportOptions {
+            device_name: Some("iphone".to_string()),
+            ..ImportOptions::default()
+        };
+        let (summary, groups) = parse(&opts);
+        assert_eq!((summary.items, summary.reserved), (5, 0));
+        assert!(groups.keys().all(|(device, _)| device == "iphone"));
+    }
+
+    #[test]
+    fn group_for_needs_a_date() {
+        let opts = ImportOptions::default();
+        let obj = |value: JsonValue| value.as_object().unwrap().clone();
+        assert_eq!(
+            group_for(
+                &obj(json!({ "sourceName": "Watch", "startDate": "2026-09-01 23:30:00 -0100" })),
+                &opts
+            ),
+            Some(("Watch".to_string(), day("2026-09-02")))
+        );
+        assert_eq!(
+            group_for(&obj(json!({ "sourceName": "Watch" })), &opts),
+            None
+        );
+        assert_eq!(
+            group_for(
+                &obj(json!({ "sourceName": "Watch", "dateComponents": "Sept 1" })),
+                &opts
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn import_identity_ignores_values_and_undated_items() {
+        let record = json!({
+            "kind": "Record",
+            "type": "HKQuantityTypeIdentifierStepCount",
+            "sourceName": "Phone",
+            "startDate": "2026-09-01 08:00:00 +0000",
+            "endDate": "2026-09-01 08:10:00 +0000",
+            "value": 10.0,
+        });
+        let mut same = record.clone();
+        same["value"] = json!(11.0);
+        let mut later = record.clone();
+        later["endDate"] = json!("2026-09-01 08:20:00 +0000");
+        assert_eq!(import_identity(&record), import_identity(&same));
+        assert_ne!(import_identity(&record), import_identity(&later));
+        assert_eq!(import_identity(&json!({ "kind": "Record" })), None);
+
+        let point = json!({ "header": { "uuid": "abc" }, "body": {} });
+        assert_eq!(import_identity(&point).as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn import_jobs_track_status_and_forget_old_imports() {
+        let jobs = ImportJobs::default();
+        let first = jobs.start(None);
+        assert!(matches!(
+            jobs.get(&first, None),
+            Some(ImportStatus::Running)
+        ));
+        jobs.finish(&first, ImportStatus::Finished(ImportSummary::default()));
+        assert!(matches!(
+            jobs.get(&first, None),
+            Some(ImportStatus::Finished(_))
+        ));
+
+        let running = jobs.start(None);
+        for _ in 0..KEPT_JOBS {
+            let id = jobs.start(None);
+            jobs.finish(
+                &id,
+                ImportStatus::Failed {
+                    error: "boom".to_string(),
+                },
+            );
+        }
+        assert!(jobs.get(&first, None).is_none());
+        assert!(matches!(
+            jobs.get(&running, None),
+            Some(ImportStatus::Running)
+        ));
+        assert!(jobs.get("unknown", None).is_none());
+    }
+
+    #[test]
+    fn import_jobs_are_visible_to_their_owner_only() {
+        let jobs = ImportJobs::default();
+        let id = jobs.start(Some("alice"));
+        assert!(jobs.get(&id, Some("alice")).is_some());
+        assert!(jobs.get(&id, Some("bob")).is_none());
+        assert!(jobs.get(&id, None).is_none());
+    }
+}
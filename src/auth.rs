@@ -1,54 +1,322 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use axum::{
-    extract::{Request, State},
-    http::{StatusCode, header},
+    Extension,
+    body::{Body, to_bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode, header},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
 use base64::Engine as _;
 use base64::engine::general_purpose::STANDARD as B64;
 
+use crate::s3;
+use crate::scopes::{Scope, Scopes};
 use crate::state::AppState;
+use crate::tls::ClientCertUser;
 use tracing::debug;
 
-// Basic auth middleware; if credentials configured, enforce; otherwise allow.
-pub async fn basic_auth(
+const API_KEY_HEADER: &str = "x-api-key";
+const SIGNATURE_HEADER: &str = "x-signature";
+const TIMESTAMP_HEADER: &str = "x-timestamp";
+const CLIENT_ID_HEADER: &str = "x-client-id";
+
+// User names become key prefixes; `_` prefixes are reserved for internal trees.
+const RESERVED_USER: (StatusCode, &str) = (StatusCode::FORBIDDEN, "reserved user name");
+
+// Signed bodies are buffered in memory to compute the HMAC, so they get no more room
+// than axum's default request body limit, which bounds `/ingest` bodies.
+const MAX_SIGNED_BODY: usize = 2 * 1024 * 1024;
+
+// Auth middleware: accepts a verified TLS client certificate, basic auth, a bearer
+// JWT, an API key (Bearer or X-API-Key) or an HMAC-signed request when any of them
+// is configured; otherwise allows every request.
+pub async fn authenticate(
     State(state): State<AppState>,
     mut req: Request,
     next: Next,
-) -> Result<Response, (StatusCode, &'static str)> {
-    if let Some(expected) = &state.basic_auth {
-        debug!("basic auth required for this route");
-        let auth = req
-            .headers()
+) -> Result<Response, Response> {
+    if let Some(ClientCertUser(name)) = req.extensions().get::<ClientCertUser>().cloned() {
+        debug!(user = %name, "client certificate auth success");
+        if s3::is_reserved_segment(&name) {
+            debug!(user = %name, "reserved user name");
+            return Err(RESERVED_USER.into_response());
+        }
+        let scopes = state
+            .client_cert_scopes
+            .as_ref()
+            .and_then(|map| map.get(&name))
+            .unwrap_or(Scopes::INGEST);
+        req.extensions_mut().insert(AuthUser {
+            name,
+            devices: None,
+            scopes,
+        });
+        return Ok(next.run(req).await);
+    }
+    if !state.auth_configured() {
+        debug!("no auth configured; allowing request");
+        return Ok(next.run(req).await);
+    }
+    debug!("auth required for this route");
+
+    let attempt = Attempt::new(&req);
+    if let Some(guard) = &state.auth_guard
+        && let Some(retry_after) = guard.locked(&attempt.keys)
+    {
+        debug!(keys = ?attempt.keys, ?retry_after, "auth locked out");
+        return Err(locked_out(retry_after));
+    }
+    match identify(&state, req).await {
+        Ok((_, user)) if s3::is_reserved_segment(&user.name) => {
+            debug!(user = %user.name, "reserved user name");
+            Err(RESERVED_USER.into_response())
+        }
+        Ok((mut req, user)) => {
+            if let Some(guard) = &state.auth_guard
+                && let Some(key) = &attempt.user_key
+            {
+                guard.record_success(key);
+            }
+            req.extensions_mut().insert(user);
+            Ok(next.run(req).await)
+        }
+        Err(rejection) => {
+            // Requests without any credentials are not counted as failures
+            if rejection.0 == StatusCode::UNAUTHORIZED
+                && attempt.presented
+                && let Some(guard) = &state.auth_guard
+            {
+                let delay = guard.record_failure(&attempt.keys);
+                tokio::time::sleep(delay).await;
+            }
+            Err(rejection.into_response())
+        }
+    }
+}
+
+async fn identify(
+    state: &AppState,
+    req: Request,
+) -> Result<(Request, AuthUser), (StatusCode, &'static str)> {
+    if state.signing.is_some() && req.headers().contains_key(SIGNATURE_HEADER) {
+        let (req, user) = signed_request(state, req).await?;
+        debug!(user = %user.name, "signature auth success");
+        return Ok((req, user));
+    }
+    if let Some(user) = basic_user(state, req.headers()).await {
+        debug!(user = %user.name, "basic auth success");
+        return Ok((req, user));
+    }
+    if let Some(user) = jwt_user(state, req.headers()).await {
+        debug!(user = %user.name, "JWT auth success");
+        return Ok((req, user));
+    }
+    if let Some(user) = api_key_user(state, req.headers()) {
+        debug!(user = %user.name, "API key auth success");
+        return Ok((req, user));
+    }
+    debug!("auth failed or missing header");
+    Err((StatusCode::UNAUTHORIZED, "unauthorized"))
+}
+
+// Keys under which a failed attempt is counted: the client IP and, when the
+// credentials name one, the username or signing client id.
+struct Attempt {
+    keys: Vec<String>,
+    user_key: Option<String>,
+    presented: bool,
+}
+
+impl Attempt {
+    fn new(req: &Request) -> Self {
+        let headers = req.headers();
+        let presented = [
+            header::AUTHORIZATION.as_str(),
+            API_KEY_HEADER,
+            SIGNATURE_HEADER,
+        ]
+        .iter()
+        .any(|h| headers.contains_key(*h));
+        let claimed = headers
             .get(header::AUTHORIZATION)
-            .and_then(|v| v.to_str().ok());
-        if let Some(auth) = auth
-            && let Some(creds) = auth.strip_prefix("Basic ")
-            && let Ok(bytes) = B64.decode(creds)
-            && let Ok(text) = String::from_utf8(bytes)
-            && text == expected.as_str()
-        {
-            // expected is "user:pass"
-            // Optionally pass username to handlers
-            let user = extract_user(expected);
-            debug!(user = %user, "basic auth success");
-            req.extensions_mut().insert(BasicUser(user));
-            return Ok(next.run(req).await);
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Basic "))
+            .and_then(|creds| B64.decode(creds).ok())
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|text| text.split_once(':').map(|(user, _)| user.to_string()))
+            .or_else(|| {
+                headers
+                    .get(CLIENT_ID_HEADER)
+                    .filter(|_| headers.contains_key(SIGNATURE_HEADER))
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string)
+            });
+        let user_key = claimed.map(|user| format!("user:{user}"));
+        let ip_key = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| format!("ip:{}", addr.ip()));
+        Self {
+            keys: ip_key.into_iter().chain(user_key.clone()).collect(),
+            user_key,
+            presented,
         }
-        debug!("basic auth failed or missing header");
-        return Err((StatusCode::UNAUTHORIZED, "unauthorized"));
     }
-    debug!("no basic auth configured; allowing request");
+}
+
+// Scope check per route group; layered inside `authenticate`. Without auth
+// configured there is no AuthUser and every route stays open.
+pub async fn require_scope(
+    State(scope): State<Scope>,
+    user: Option<Extension<AuthUser>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, &'static str)> {
+    match user {
+        Some(Extension(user)) if !user.scopes.allows(scope) => {
+            debug!(user = %user.name, scopes = %user.scopes, required = ?scope, "insufficient scope");
+            return Err((StatusCode::FORBIDDEN, "insufficient scope"));
+        }
+        // Admin routes stay closed unless some credential is configured and presented.
+        None if scope == Scope::Admin => {
+            debug!("admin route without credentials");
+            return Err((StatusCode::FORBIDDEN, "authentication required"));
+        }
+        _ => {}
+    }
     Ok(next.run(req).await)
 }
 
+fn locked_out(retry_after: Duration) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(
+            header::RETRY_AFTER,
+            retry_after.as_secs().max(1).to_string(),
+        )],
+        "too many failed attempts",
+    )
+        .into_response()
+}
+
+async fn basic_user(state: &AppState, headers: &HeaderMap) -> Option<AuthUser> {
+    let users = state.users.as_ref()?;
+    let creds = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let text = String::from_utf8(B64.decode(creds).ok()?).ok()?;
+    let (user, pass) = text.split_once(':')?;
+    let scopes = users.verify_async(user, pass).await?;
+    Some(AuthUser {
+        name: user.to_string(),
+        devices: None,
+        scopes,
+    })
+}
+
+// Verify `X-Signature` over `X-Timestamp` + "." + body for the `X-Client-Id` client,
+// rejecting timestamps outside the allowed skew so captured requests cannot be replayed.
+async fn signed_request(
+    state: &AppState,
+    req: Request,
+) -> Result<(Request, AuthUser), (StatusCode, &'static str)> {
+    const UNAUTHORIZED: (StatusCode, &str) = (StatusCode::UNAUTHORIZED, "unauthorized");
+    let Some(clients) = state.signing.as_ref() else {
+        return Err(UNAUTHORIZED);
+    };
+    // Borrow only the headers: a &Request held across an await makes the future !Send.
+    let header_str = |headers: &HeaderMap, name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let headers = req.headers();
+    let (Some(signature), Some(timestamp), Some(client)) = (
+        header_str(headers, SIGNATURE_HEADER),
+        header_str(headers, TIMESTAMP_HEADER),
+        header_str(headers, CLIENT_ID_HEADER),
+    ) else {
+        debug!("signed request missing timestamp or client id");
+        return Err(UNAUTHORIZED);
+    };
+    let now = chrono::Utc::now().timestamp();
+    let fresh = timestamp
+        .parse::<i64>()
+        .is_ok_and(|ts| now.abs_diff(ts) <= state.signature_max_skew_secs);
+    if !fresh {
+        debug!(client = %client, timestamp = %timestamp, "stale or invalid signature timestamp");
+        return Err(UNAUTHORIZED);
+    }
+
+    let (parts, body) = req.into_parts();
+    let bytes = to_bytes(body, MAX_SIGNED_BODY)
+        .await
+        .map_err(|_| (StatusCode::PAYLOAD_TOO_LARGE, "payload too large"))?;
+    if !clients.verify(&client, &timestamp, &bytes, &signature) {
+        debug!(client = %client, "signature mismatch");
+        return Err(UNAUTHORIZED);
+    }
+    // Signing clients are webhook relays: they only ever get the ingest scope, whatever
+    // the client, so they cannot read data back or reach admin routes.
+    let user = AuthUser {
+        name: client,
+        devices: None,
+        scopes: Scopes::INGEST,
+    };
+    Ok((Request::from_parts(parts, Body::from(bytes)), user))
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+// API keys never contain dots, so a three-part bearer token is taken as a JWT.
+async fn jwt_user(state: &AppState, headers: &HeaderMap) -> Option<AuthUser> {
+    let jwt = state.jwt.as_ref()?;
+    let token = bearer_token(headers).filter(|t| t.split('.').count() == 3)?;
+    jwt.verify(token).await.map(|(name, scopes)| AuthUser {
+        name,
+        devices: None,
+        scopes,
+    })
+}
+
+fn api_key_user(state: &AppState, headers: &HeaderMap) -> Option<AuthUser> {
+    let keys = state.api_keys.as_ref()?;
+    let token = bearer_token(headers).or_else(|| headers.get(API_KEY_HEADER)?.to_str().ok())?;
+    let key = keys.lookup(token.trim())?;
+    Some(AuthUser {
+        name: key.user.clone(),
+        devices: key.devices.clone(),
+        scopes: key.scopes,
+    })
+}
+
+/// The authenticated caller; handlers use the name to scope object keys.
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
-pub struct BasicUser(pub String);
+pub struct AuthUser {
+    pub name: String,
+    /// Devices an API key is pinned to; `None` allows every device.
+    pub devices: Option<Arc<[String]>>,
+    pub scopes: Scopes,
+}
 
-fn extract_user(userpass: &str) -> String {
-    match userpass.split_once(':') {
-        Some((u, _)) => u.to_string(),
-        None => String::new(),
+impl AuthUser {
+    pub fn allows_device(&self, device_name: &str) -> bool {
+        self.devices
+            .as_ref()
+            .is_none_or(|devices| devices.iter().any(|d| d == device_name))
     }
 }
@@ -17,16 +17,41 @@ struct Args {
     /// Timeout in milliseconds
     #[arg(long, env = "AHE_HEALTH_TIMEOUT_MS", default_value_t = 1500)]
     timeout_ms: u64,
+
+    /// Skip TLS certificate verification (for self-signed certificates on https URLs)
+    #[arg(long, env = "AHE_HEALTH_INSECURE", default_value_t = false)]
+    insecure: bool,
+
+    /// PEM file with a client certificate and its private key, presented on https URLs
+    /// (needed when the server requires client certificates)
+    #[arg(long, env = "AHE_HEALTH_CLIENT_CERT")]
+    client_cert: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
-    let client = reqwest::Client::builder()
+    let mut builder = reqwest::Client::builder()
         .user_agent("ahe-healthcheck/1")
         .timeout(Duration::from_millis(args.timeout_ms))
-        .build();
+        .danger_accept_invalid_certs(args.insecure);
+    if let Some(path) = &args.client_cert {
+        let identity = std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|pem| reqwest::Identity::from_pem(&pem).map_err(|err| err.to_string()));
+        match identity {
+            Ok(identity) => builder = builder.identity(identity),
+            Err(err) => {
+                eprintln!(
+                    "failed to load client certificate {}: {err}",
+                    path.display()
+                );
+                std::process::exit(3);
+            }
+        }
+    }
+    let client = builder.build();
 
     let code = match client {
         Ok(client) => match client.get(&args.url).send().await {
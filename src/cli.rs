@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{BufRead, BufWriter, IsTerminal, Write};
+
+use crate::apple_export::{self, ImportOptions};
+use crate::config::{Command, HashAlgorithm};
+use crate::encryption;
+use crate::erasure::{self, Erasure};
+use crate::error::{Error, Result};
+use crate::fhir;
+use crate::migrate;
+use crate::s3;
+use crate::scopes::Scopes;
+use crate::state::AppState;
+use crate::{api_keys, users};
+
+// One-off commands sharing the server configuration and S3 client.
+pub async fn run(command: Command, state: &AppState) -> Result<()> {
+    match command {
+        Command::ImportApple {
+            user,
+            file,
+            device_name,
+        } => {
+            let opts = ImportOptions { user, device_name };
+            let summary = apple_export::import_file(state, &file, opts).await?;
+            println!(
+                "imported {} items and {} workout routes into {} day file writes ({} skipped, {} from reserved sources, {} already imported)",
+                summary.items,
+                summary.routes,
+                summary.writes,
+                summary.skipped,
+                summary.reserved,
+                summary.duplicates
+            );
+        }
+        Command::ExportFhir {
+            user,
+            device,
+            from,
+            to,
+            output,
+        } => {
+            let items =
+                s3::load_day_range(state, user.as_deref(), &device, from, to.unwrap_or(from))
+                    .await?;
+            let bundle = fhir::bundle(&device, &items);
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            serde_json::to_writer_pretty(&mut out, &bundle)?;
+            writeln!(out)?;
+            out.flush()?;
+        }
+        Command::MigrateLegacyLayout { user, dry_run } => {
+            let report = migrate::migrate_legacy_layout(state, &user, dry_run).await?;
+            for (from, to) in &report.moved {
+                println!("{from}\t{to}");
+            }
+            let verb = if dry_run { "would move" } else { "moved" };
+            println!(
+                "{verb} {} files ({} merged into existing files), {} failed",
+                report.moved.len(),
+                report.merged,
+                report.failed.len()
+            );
+            for key in &report.failed {
+                eprintln!("failed to move {key}");
+            }
+            for key in &report.undeleted {
+                eprintln!("copied but could not delete {key}; delete it before rerunning");
+            }
+        }
+        Command::DeleteData {
+            user,
+            device,
+            from,
+            to,
+            dry_run,
+        } => {
+            let erasure = Erasure {
+                user,
+                device,
+                from,
+                to,
+                dry_run,
+            };
+            erasure
+                .validate(state.auth_configured())
+                .map_err(|msg| Error::Config(msg.to_string()))?;
+            let report = erasure::erase(state, &erasure, "cli").await?;
+            for obj in &report.objects {
+                println!("{}\t{}", obj.size, obj.key);
+            }
+            let verb = if report.dry_run {
+                "would delete"
+            } else {
+                "deleted"
+            };
+            println!(
+                "{verb} {} objects ({} bytes)",
+                report.objects.len() - report.failed.len(),
+                report.bytes
+            );
+            for key in &report.failed {
+                eprintln!("failed to delete {key}");
+            }
+            if let Some(audit_key) = report.audit_key {
+                println!("audit entry: {audit_key}");
+            }
+        }
+        Command::RotateEncryptionKey => {
+            let report = encryption::rotate(state).await?;
+            for key in &report.failed {
+                eprintln!("failed to re-encrypt {key}");
+            }
+            for key in &report.conflicts {
+                eprintln!("skipped {key}: changed while rotating");
+            }
+            println!(
+                "re-encrypted {} objects ({} previously unencrypted), {} already current, {} skipped, {} failed",
+                report.reencrypted + report.plaintext,
+                report.plaintext,
+                report.unchanged,
+                report.conflicts.len(),
+                report.failed.len()
+            );
+        }
+        Command::GenerateEncryptionKey { id } => generate_encryption_key(&id)?,
+        Command::HashPassword { algorithm } => hash_password(algorithm)?,
+        Command::GenerateApiKey {
+            user,
+            devices,
+            scopes,
+        } => generate_api_key(&user, &devices, &scopes)?,
+    }
+    Ok(())
+}
+
+// Reads the password from stdin so it never appears in shell history or process lists.
+pub fn hash_password(algorithm: HashAlgorithm) -> Result<()> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("Password: ");
+    }
+    let mut line = String::new();
+    stdin.lock().read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(Error::PasswordHash("empty password".to_string()));
+    }
+    println!("{}", users::hash_password(password, algorithm)?);
+    Ok(())
+}
+
+// The token is shown once; only its digest goes into the API keys file.
+pub fn generate_api_key(user: &str, devices: &[String], scopes: &str) -> Result<()> {
+    if user.is_empty() || user.contains(':') {
+        return Err(Error::Config(
+            "user must be non-empty and contain no ':'".to_string(),
+        ));
+    }
+    let scopes = Scopes::parse_list(scopes).ok_or_else(|| {
+        Error::Config(format!(
+            "invalid scopes {scopes:?}; expected ingest, read, admin"
+        ))
+    })?;
+    let token = api_keys::generate_token();
+    let mut entry = format!("{user}:{}", api_keys::digest(&token));
+    if !devices.is_empty() || scopes != Scopes::INGEST {
+        entry.push(':');
+        entry.push_str(&devices.join(","));
+    }
+    if scopes != Scopes::INGEST {
+        entry.push(':');
+        entry.push_str(&scopes.to_string());
+    }
+    println!("token: {token}");
+    println!("entry: {entry}");
+    Ok(())
+}
+
+// Print the key file entry only; the key is never stored by the server itself.
+pub fn generate_encryption_key(id: &str) -> Result<()> {
+    println!("{}", encryption::generate_key_entry(id)?);
+    Ok(())
+}
@@ -1,6 +1,10 @@
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 use std::string::ToString;
 
+use crate::tls::TlsSettings;
+
 #[derive(Parser, Debug, Clone)]
 #[command(name = "apple-health-export")]
 #[command(about = "Axum service to ingest JSON and merge to S3 by day", version)]
@@ -25,14 +29,158 @@ pub struct Config {
     #[arg(long, env = "AHE_BASIC_USER")]
     pub basic_user: Option<String>,
 
-    /// Basic auth password
+    /// Basic auth password, in plaintext or as an argon2id/bcrypt hash (see `hash-password`)
     #[arg(long, env = "AHE_BASIC_PASS")]
     pub basic_pass: Option<String>,
 
+    /// Scopes of the AHE_BASIC_USER credential: comma-separated ingest, read, admin
+    #[arg(long, env = "AHE_BASIC_SCOPES", default_value = "ingest")]
+    pub basic_scopes: String,
+
+    /// File with one "username:password" or "username:hash:scopes" per line; each user's data is stored under its own prefix
+    #[arg(long, env = "AHE_USERS_FILE")]
+    pub users_file: Option<PathBuf>,
+
+    /// File with one "username:sha256[:device,...]" API key per line, accepted as a Bearer token or X-API-Key
+    #[arg(long, env = "AHE_API_KEYS_FILE")]
+    pub api_keys_file: Option<PathBuf>,
+
+    /// JWKS used to validate bearer JWTs: a file path or an http(s) URL
+    #[arg(long, env = "AHE_JWT_JWKS")]
+    pub jwt_jwks: Option<String>,
+
+    /// Required JWT issuer (`iss`)
+    #[arg(long, env = "AHE_JWT_ISSUER")]
+    pub jwt_issuer: Option<String>,
+
+    /// Required JWT audience (`aud`)
+    #[arg(long, env = "AHE_JWT_AUDIENCE")]
+    pub jwt_audience: Option<String>,
+
+    /// JWT claim holding the user identity used for key prefixes
+    #[arg(long, env = "AHE_JWT_USER_CLAIM", default_value = "sub")]
+    pub jwt_user_claim: String,
+
+    /// Seconds a fetched JWKS is cached before it is reloaded
+    #[arg(long, env = "AHE_JWT_JWKS_TTL_SECS", default_value_t = 300)]
+    pub jwt_jwks_ttl_secs: u64,
+
+    /// File with one "client_id:secret" per line for HMAC-signed requests (X-Signature)
+    #[arg(long, env = "AHE_SIGNING_CLIENTS_FILE")]
+    pub signing_clients_file: Option<PathBuf>,
+
+    /// Maximum age in seconds of a signed request's X-Timestamp
+    #[arg(long, env = "AHE_SIGNATURE_MAX_SKEW_SECS", default_value_t = 300)]
+    pub signature_max_skew_secs: u64,
+
+    /// PEM certificate chain; enables built-in TLS together with --tls-key
+    #[arg(long, env = "AHE_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "AHE_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+
+    /// PEM CA bundle for client certificates (mTLS); the subject CN becomes the user
+    #[arg(long, env = "AHE_TLS_CLIENT_CA")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// File with one "common_name:scopes" per line giving client certificates scopes other than ingest
+    #[arg(long, env = "AHE_TLS_CLIENT_SCOPES_FILE")]
+    pub tls_client_scopes_file: Option<PathBuf>,
+
+    /// Reject TLS connections without a valid client certificate
+    #[arg(long, env = "AHE_TLS_CLIENT_CERT_REQUIRED", default_value_t = false)]
+    pub tls_client_cert_required: bool,
+
+    /// Failed auth attempts per client IP or username before a lockout (0 disables)
+    #[arg(long, env = "AHE_AUTH_MAX_FAILURES", default_value_t = 10)]
+    pub auth_max_failures: u32,
+
+    /// Seconds a locked-out client IP or username is rejected
+    #[arg(long, env = "AHE_AUTH_LOCKOUT_SECS", default_value_t = 900)]
+    pub auth_lockout_secs: u64,
+
+    /// Sustained /ingest requests per second allowed per user (or client IP without auth)
+    #[arg(long, env = "AHE_RATE_LIMIT_PER_SEC")]
+    pub rate_limit_per_sec: Option<f64>,
+
+    /// Requests a client may burst above the sustained rate
+    #[arg(long, env = "AHE_RATE_LIMIT_BURST", default_value_t = 20)]
+    pub rate_limit_burst: u32,
+
+    /// Maximum /ingest body bytes per user per UTC day
+    #[arg(long, env = "AHE_QUOTA_DAILY_BYTES")]
+    pub quota_daily_bytes: Option<u64>,
+
+    /// Maximum ingested items per user per UTC day
+    #[arg(long, env = "AHE_QUOTA_DAILY_ITEMS")]
+    pub quota_daily_items: Option<u64>,
+
+    /// Retention rules: comma-separated "<target>=<days>[:delete|downsample]" with target *, device:<name> or metric:<name>
+    #[arg(long, env = "AHE_RETENTION")]
+    pub retention: Option<String>,
+
+    /// Seconds between retention sweeps
+    #[arg(
+        long,
+        env = "AHE_RETENTION_SWEEP_INTERVAL_SECS",
+        default_value_t = 3600
+    )]
+    pub retention_sweep_interval_secs: u64,
+
+    /// Metrics moved to the rollup tier as aggregates: comma-separated "<metric>=minute|five_minutes"
+    #[arg(long, env = "AHE_ROLLUP")]
+    pub rollup: Option<String>,
+
+    /// Age in days after which raw samples of rollup metrics are replaced by aggregates
+    #[arg(long, env = "AHE_ROLLUP_AFTER_DAYS", default_value_t = 7)]
+    pub rollup_after_days: u32,
+
+    /// Seconds between rollup runs
+    #[arg(long, env = "AHE_ROLLUP_INTERVAL_SECS", default_value_t = 3600)]
+    pub rollup_interval_secs: u64,
+
+    /// File with one "key_id:base64-key" AES-256 master key per line; when set, stored objects are encrypted with per-object data keys wrapped by the first key
+    #[arg(long, env = "AHE_ENCRYPTION_KEY_FILE")]
+    pub encryption_key_file: Option<PathBuf>,
+
+    /// S3 server-side encryption requested for written objects
+    #[arg(long, env = "AHE_SSE", value_enum)]
+    pub sse: Option<SseMode>,
+
+    /// KMS key id or ARN for --sse sse-kms (default: the AWS managed key for S3)
+    #[arg(long, env = "AHE_SSE_KMS_KEY_ID")]
+    pub sse_kms_key_id: Option<String>,
+
+    /// File holding the base64 encoded 256-bit key for --sse sse-c
+    #[arg(long, env = "AHE_SSE_CUSTOMER_KEY_FILE")]
+    pub sse_customer_key_file: Option<PathBuf>,
+
+    /// S3 storage class of written objects, e.g. STANDARD_IA or INTELLIGENT_TIERING
+    #[arg(long, env = "AHE_STORAGE_CLASS")]
+    pub storage_class: Option<String>,
+
+    /// Tags added to every written object: comma-separated "key=value"
+    #[arg(long, env = "AHE_OBJECT_TAGS")]
+    pub object_tags: Option<String>,
+
+    /// Record user, device, item count and schema version in the metadata of written objects
+    #[arg(long, env = "AHE_OBJECT_METADATA", default_value_t = false)]
+    pub object_metadata: bool,
+
     /// Queue capacity for background ingestion
     #[arg(long, env = "AHE_QUEUE_CAP", default_value_t = 1024)]
     pub queue_cap: usize,
 
+    /// Largest upload accepted by /import/apple, in bytes
+    #[arg(long, env = "AHE_IMPORT_MAX_BYTES", default_value_t = 4 * 1024 * 1024 * 1024)]
+    pub import_max_bytes: u64,
+
+    /// Apple Health imports that may run at the same time
+    #[arg(long, env = "AHE_IMPORT_CONCURRENCY", default_value_t = 2)]
+    pub import_concurrency: usize,
+
     /// Number of background worker tasks
     #[arg(long, env = "AHE_WORKERS", default_value_t = 1)]
     pub workers: usize,
@@ -40,6 +188,166 @@ pub struct Config {
     /// Use S3 path-style addressing (useful for MinIO/localstack)
     #[arg(long, env = "AHE_S3_PATH_STYLE", default_value_t = true)]
     pub s3_path_style: bool,
+
+    /// Convert known metrics to canonical SI units (m, kg, kJ, degC, m/s) before storing
+    #[arg(long, env = "AHE_NORMALIZE_UNITS", default_value_t = false)]
+    pub normalize_units: bool,
+
+    /// Format of stored day files: raw ingested items or Open mHealth data points
+    #[arg(long, env = "AHE_STORAGE_FORMAT", value_enum, default_value_t = StorageFormat::Raw)]
+    pub storage_format: StorageFormat,
+
+    /// Run a one-off command instead of the HTTP server
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Config {
+    /// TLS settings, if both a certificate and a key are configured.
+    pub fn tls(&self) -> Result<Option<TlsSettings>, String> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some(TlsSettings {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: self.tls_client_ca.clone(),
+                client_cert_required: self.tls_client_cert_required,
+            })),
+            (None, None) if self.tls_client_ca.is_none() => Ok(None),
+            (None, None) => Err("--tls-client-ca requires --tls-cert and --tls-key".to_string()),
+            _ => Err("--tls-cert and --tls-key must be set together".to_string()),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageFormat {
+    /// Store items exactly as received
+    Raw,
+    /// Convert items to Open mHealth data points before storing
+    Omh,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SseMode {
+    /// S3 managed keys (SSE-S3)
+    SseS3,
+    /// AWS KMS keys (SSE-KMS)
+    SseKms,
+    /// Customer-provided key sent with every request (SSE-C)
+    SseC,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Import an Apple Health "Export All Health Data" archive (export.zip or export.xml)
+    ImportApple {
+        /// User whose prefix receives the data (as when authenticated as this user)
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Path to export.zip or a bare export.xml
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Store every item under this device instead of each record's source name
+        #[arg(long)]
+        device_name: Option<String>,
+    },
+
+    /// Export a device's stored items as a FHIR R4 Bundle of Observations
+    ExportFhir {
+        /// User whose data is exported
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Device whose day files are exported
+        #[arg(long)]
+        device: String,
+
+        /// First day to export (YYYY-MM-DD)
+        #[arg(long)]
+        from: NaiveDate,
+
+        /// Last day to export, inclusive (defaults to --from)
+        #[arg(long)]
+        to: Option<NaiveDate>,
+
+        /// Write the Bundle to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Move day files stored without authentication under a user's prefix, e.g. the AHE_BASIC_USER's after enabling auth
+    MigrateLegacyLayout {
+        /// User whose prefix receives the data
+        #[arg(long)]
+        user: String,
+
+        /// List the files that would be moved without moving them
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Delete a user's stored data (one day, a date range, a device, or everything) and record an audit entry
+    DeleteData {
+        /// User whose data is deleted
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Only delete this device's data
+        #[arg(long)]
+        device: Option<String>,
+
+        /// First day to delete (YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<NaiveDate>,
+
+        /// Last day to delete, inclusive
+        #[arg(long)]
+        to: Option<NaiveDate>,
+
+        /// List the objects that would be deleted without deleting them
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Re-encrypt stored objects that are unencrypted or use a key other than the first in the key file
+    RotateEncryptionKey,
+
+    /// Generate a random master key and print its encryption key file entry
+    GenerateEncryptionKey {
+        /// Key id recorded with every object the key encrypts
+        #[arg(long)]
+        id: String,
+    },
+
+    /// Hash a password read from stdin for AHE_BASIC_PASS or the users file
+    HashPassword {
+        /// Hash algorithm
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Argon2id)]
+        algorithm: HashAlgorithm,
+    },
+
+    /// Generate an API key and print it with its API keys file entry
+    GenerateApiKey {
+        /// User the key authenticates as
+        #[arg(long)]
+        user: String,
+
+        /// Restrict the key to this device_name (repeatable)
+        #[arg(long = "device")]
+        devices: Vec<String>,
+
+        /// Comma-separated scopes granted to the key (ingest, read, admin)
+        #[arg(long, default_value = "ingest")]
+        scopes: String,
+    },
 }
 
 pub fn normalize_prefix(mut p: String) -> String {
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as B64;
+use tracing::{debug, info, instrument};
+
+use crate::error::{Error, Result};
+use crate::maintenance;
+use crate::s3;
+use crate::state::AppState;
+
+/// Object metadata naming the encryption scheme; unencrypted objects have none.
+pub const META_SCHEME: &str = "ahe-encryption";
+/// Object metadata naming the master key that wrapped the data key.
+pub const META_KEY_ID: &str = "ahe-key-id";
+/// Object metadata holding the wrapped data key (base64 of nonce and ciphertext).
+pub const META_DATA_KEY: &str = "ahe-data-key";
+
+const SCHEME: &str = "aes-256-gcm";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Master keys from the key file. The first key wraps the data keys of new objects;
+/// the others are kept to read objects written before a rotation.
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+/// Encryption metadata stored with an object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub key_id: String,
+    /// Data key wrapped by the master key `key_id`.
+    pub data_key: Vec<u8>,
+}
+
+/// Outcome of a key rotation.
+#[derive(Debug, Default)]
+pub struct RotationReport {
+    pub reencrypted: u64,
+    /// Objects that were stored unencrypted.
+    pub plaintext: u64,
+    pub unchanged: u64,
+    /// Objects rewritten by someone else between read and write; a rerun picks them up.
+    pub conflicts: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl Keyring {
+    /// Load a key file with one `key_id:base64-key` entry per line, each key 32 random
+    /// bytes. Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut active = None;
+        let mut keys = HashMap::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                |msg: &str| Error::Config(format!("{}:{}: {msg}", path.display(), idx + 1));
+            let (id, key) = line
+                .split_once(':')
+                .ok_or_else(|| invalid("expected key_id:base64-key"))?;
+            if !valid_key_id(id) {
+                return Err(invalid("key id must be non-empty [A-Za-z0-9._-]"));
+            }
+            let key = B64
+                .decode(key.trim())
+                .ok()
+                .filter(|k| k.len() == KEY_LEN)
+                .ok_or_else(|| invalid("key must be 32 bytes, base64 encoded"))?;
+            let cipher = Aes256Gcm::new_from_slice(&key).expect("32-byte key");
+            if keys.insert(id.to_string(), cipher).is_some() {
+                return Err(invalid("duplicate key id"));
+            }
+            active.get_or_insert_with(|| id.to_string());
+        }
+        let active = active
+            .ok_or_else(|| Error::Config(format!("{}: no encryption keys", path.display())))?;
+        debug!(path = %path.display(), keys = keys.len(), %active, "encryption key file loaded");
+        Ok(Self { active, keys })
+    }
+
+    /// Id of the key wrapping new data keys.
+    pub fn active_id(&self) -> &str {
+        &self.active
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Encrypt `plaintext` under a fresh data key wrapped by the active master key. The
+    /// object key is authenticated with the body, so a ciphertext copied to another key
+    /// fails to decrypt.
+    pub fn encrypt(&self, object_key: &str, plaintext: &[u8]) -> Result<(Vec<u8>, Envelope)> {
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let body = seal(&Aes256Gcm::new(&data_key), plaintext, object_key.as_bytes())?;
+        let data_key = seal(&self.keys[&self.active], &data_key, self.active.as_bytes())?;
+        Ok((
+            body,
+            Envelope {
+                key_id: self.active.clone(),
+                data_key,
+            },
+        ))
+    }
+
+    pub fn decrypt(
+        &self,
+        object_key: &str,
+        ciphertext: &[u8],
+        envelope: &Envelope,
+    ) -> Result<Vec<u8>> {
+        let master = self
+            .keys
+            .get(&envelope.key_id)
+            .ok_or_else(|| Error::Encryption(format!("unknown key id {:?}", envelope.key_id)))?;
+        let data_key = open(master, &envelope.data_key, envelope.key_id.as_bytes())?;
+        let cipher = Aes256Gcm::new_from_slice(&data_key)
+            .map_err(|_| Error::Encryption("invalid data key".to_string()))?;
+        open(&cipher, ciphertext, object_key.as_bytes())
+    }
+}
+
+impl Envelope {
+    /// The envelope of an object from its S3 metadata, `None` if it is not encrypted.
+    pub fn from_metadata(metadata: Option<&HashMap<String, String>>) -> Result<Option<Self>> {
+        let Some(metadata) = metadata else {
+            return Ok(None);
+        };
+        match metadata.get(META_SCHEME).map(String::as_str) {
+            None => return Ok(None),
+            Some(SCHEME) => {}
+            Some(other) => {
+                return Err(Error::Encryption(format!(
+                    "unsupported encryption scheme {other:?}"
+                )));
+            }
+        }
+        let key_id = metadata
+            .get(META_KEY_ID)
+            .ok_or_else(|| Error::Encryption("missing key id".to_string()))?;
+        let data_key = metadata
+            .get(META_DATA_KEY)
+            .and_then(|k| B64.decode(k).ok())
+            .ok_or_else(|| Error::Encryption("missing or invalid data key".to_string()))?;
+        Ok(Some(Self {
+            key_id: key_id.clone(),
+            data_key,
+        }))
+    }
+
+    pub fn metadata(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (META_SCHEME.to_string(), SCHEME.to_string()),
+            (META_KEY_ID.to_string(), self.key_id.clone()),
+            (META_DATA_KEY.to_string(), B64.encode(&self.data_key)),
+        ])
+    }
+}
+
+/// A new key file entry with a random key.
+pub fn generate_key_entry(id: &str) -> Result<String> {
+    if !valid_key_id(id) {
+        return Err(Error::Config(
+            "key id must be non-empty [A-Za-z0-9._-]".to_string(),
+        ));
+    }
+    Ok(format!(
+        "{id}:{}",
+        B64.encode(Aes256Gcm::generate_key(OsRng))
+    ))
+}
+
+// Key ids travel in S3 metadata, which only carries plain ASCII reliably.
+fn valid_key_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+// Random nonce followed by the ciphertext and tag.
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Encryption("encryption failed".to_string()))?;
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::Encryption("ciphertext too short".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = <[u8; NONCE_LEN]>::try_from(nonce)
+        .map_err(|_| Error::Encryption("invalid nonce".to_string()))?;
+    cipher
+        .decrypt(
+            &Nonce::from(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| {
+            Error::Encryption("decryption failed (wrong key or corrupted object)".to_string())
+        })
+}
+
+/// Re-encrypt every object under the prefix that is stored unencrypted or under a
+/// master key other than the active one, so retired keys can be removed from the key file.
+/// Each object is replaced only if its ETag is unchanged since it was read, so writes by
+/// a running server are never overwritten with stale contents.
+#[instrument(skip(state))]
+pub async fn rotate(state: &AppState) -> Result<RotationReport> {
+    let keyring = state
+        .encryption
+        .as_deref()
+        .ok_or_else(|| Error::Config("no encryption key file configured".to_string()))?;
+    let mut report = RotationReport::default();
+    let (_guard, objects) = maintenance::objects(state).await?;
+    for obj in objects {
+        match rotate_object(state, keyring, &obj.key).await {
+            Ok(Rotated::Plaintext) => report.plaintext += 1,
+            Ok(Rotated::Reencrypted) => report.reencrypted += 1,
+            Ok(Rotated::Unchanged) => report.unchanged += 1,
+            Ok(Rotated::Conflict) => {
+                debug!(key = %obj.key, "object changed during rotation; skipped");
+                report.conflicts.push(obj.key);
+            }
+            Err(err) => {
+                maintenance::log_failure("key_rotation", &obj.key, &err);
+                report.failed.push(obj.key);
+            }
+        }
+    }
+    info!(
+        event = "encryption_key_rotated",
+        active_key = %keyring.active_id(),
+        reencrypted = report.reencrypted,
+        plaintext = report.plaintext,
+        unchanged = report.unchanged,
+        conflicts = report.conflicts.len(),
+        failed = report.failed.len(),
+        "key rotation finished"
+    );
+    Ok(report)
+}
+
+enum Rotated {
+    Unchanged,
+    Plaintext,
+    Reencrypted,
+    Conflict,
+}
+
+// Rewrite one object under the active key unless it already uses it.
+async fn rotate_object(state: &AppState, keyring: &Keyring, key: &str) -> Result<Rotated> {
+    let obj = match s3::fetch_object(state, key, None).await? {
+        s3::Fetched::Object(obj) => obj,
+        // Deleted since the listing.
+        _ => return Ok(Rotated::Unchanged),
+    };
+    let previous = Envelope::from_metadata(obj.metadata())?.map(|e| e.key_id);
+    if previous.as_deref() == Some(keyring.active_id()) {
+        return Ok(Rotated::Unchanged);
+    }
+    let etag = obj
+        .e_tag()
+        .map(str::to_string)
+        .ok_or_else(|| Error::Encryption(format!("{key} has no ETag")))?;
+    let data = s3::read_object(state, key, *obj).await?;
+    let items = serde_json::from_slice::<serde_json::Value>(&data)
+        .ok()
+        .map(|v| v.as_array().map_or(1, Vec::len));
+    if !s3::put_object_if_unchanged(state, key, data.to_vec(), items, Some(&etag)).await? {
+        return Ok(Rotated::Conflict);
+    }
+    debug!(%key, previous_key = ?previous, "object re-encrypted");
+    Ok(match previous {
+        Some(_) => Rotated::Reencrypted,
+        None => Rotated::Plaintext,
+    })
+}
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn keyring(text: &str) -> Result<Keyring> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        Keyring::from_file(file.path())
+    }
+
+    #[test]
+    fn round_trip_and_rotation() {
+        let (k1, k2) = (
+            generate_key_entry("k1").unwrap(),
+            generate_key_entry("k2").unwrap(),
+        );
+        let old = keyring(&format!("{k1}\n")).unwrap();
+        let (ciphertext, envelope) = old.encrypt("a/phone/2026-09-01.json", b"[1,2]").unwrap();
+        assert_eq!(envelope.key_id, "k1");
+        assert_ne!(ciphertext, b"[1,2]");
+        let plaintext = old
+            .decrypt("a/phone/2026-09-01.json", &ciphertext, &envelope)
+            .unwrap();
+        assert_eq!(plaintext, b"[1,2]");
+
+        // The first key is active; older keys still decrypt.
+        let rotated = keyring(&format!("# keys\n{k2}\n\n{k1}\n")).unwrap();
+        assert_eq!(rotated.active_id(), "k2");
+        assert_eq!(rotated.len(), 2);
+        let plaintext = rotated
+            .decrypt("a/phone/2026-09-01.json", &ciphertext, &envelope)
+            .unwrap();
+        assert_eq!(plaintext, b"[1,2]");
+    }
+
+    #[test]
+    fn decrypt_fails_with_the_wrong_key_or_object_key() {
+        let ring = keyring(&format!("{}\n", generate_key_entry("k1").unwrap())).unwrap();
+        let (ciphertext, envelope) = ring.encrypt("a/phone/2026-09-01.json", b"[1]").unwrap();
+
+        // Same id, different key material.
+        let other = keyring(&format!("{}\n", generate_key_entry("k1").unwrap())).unwrap();
+        assert!(
+            other
+                .decrypt("a/phone/2026-09-01.json", &ciphertext, &envelope)
+                .is_err()
+        );
+        let unknown = keyring(&format!("{}\n", generate_key_entry("k9").unwrap())).unwrap();
+        assert!(
+            unknown
+                .decrypt("a/phone/2026-09-01.json", &ciphertext, &envelope)
+                .is_err()
+        );
+        assert!(
+            ring.decrypt("b/phone/2026-09-01.json", &ciphertext, &envelope)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_key_files() {
+        let entry = generate_key_entry("k1").unwrap();
+        assert!(keyring("# no keys\n").is_err());
+        assert!(keyring(&format!("{entry}\n{entry}\n")).is_err());
+        assert!(keyring("k1:c2hvcnQ=\n").is_err());
+        assert!(keyring(&entry.replacen("k1", "k/1", 1)).is_err());
+        assert!(generate_key_entry("").is_err());
+    }
+
+    #[test]
+    fn envelope_metadata_round_trip() {
+        let envelope = Envelope {
+            key_id: "k1".to_string(),
+            data_key: vec![1, 2, 3],
+        };
+        let metadata = envelope.metadata();
+        assert_eq!(
+            Envelope::from_metadata(Some(&metadata)).unwrap(),
+            Some(envelope)
+        );
+        assert_eq!(Envelope::from_metadata(None).unwrap(), None);
+
+        let mut other = metadata.clone();
+        other.insert(META_SCHEME.to_string(), "rot13".to_string());
+        assert!(Envelope::from_metadata(Some(&other)).is_err());
+        other.clear();
+        assert_eq!(Envelope::from_metadata(Some(&other)).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_truncated_ciphertext() {
+        let ring = keyring(&format!("{}\n", generate_key_entry("k1").unwrap())).unwrap();
+        let (ciphertext, envelope) = ring.encrypt("a/phone/2026-09-01.json", b"[1]").unwrap();
+        for len in [0, NONCE_LEN - 1, NONCE_LEN, ciphertext.len() - 1] {
+            assert!(
+                ring.decrypt("a/phone/2026-09-01.json", &ciphertext[..len], &envelope)
+                    .is_err(),
+                "{len}"
+            );
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::error::Result;
+use crate::maintenance;
+use crate::s3;
+use crate::state::AppState;
+
+/// Directory under the configured prefix holding one audit entry per erasure.
+pub const AUDIT_DIR: &str = "_audit";
+
+/// What to erase: all data of a user, or of one device, optionally limited to
+/// an inclusive range of days.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Erasure {
+    pub user: Option<String>,
+    pub device: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    /// List what would be deleted without deleting it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasedObject {
+    pub key: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErasureReport {
+    pub dry_run: bool,
+    pub objects: Vec<ErasedObject>,
+    pub bytes: i64,
+    /// Keys S3 reported as not deleted.
+    pub failed: Vec<String>,
+    /// Audit entry written for a real erasure.
+    pub audit_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    at: chrono::DateTime<Utc>,
+    actor: &'a str,
+    user: Option<&'a str>,
+    device: Option<&'a str>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    deleted: Vec<&'a ErasedObject>,
+    bytes: i64,
+    failed: &'a [String],
+}
+
+impl Erasure {
+    /// Reject requests that would reach beyond one user or device. With
+    /// authentication configured a device without a user would name a user prefix,
+    /// so the user is required.
+    pub fn validate(&self, auth_configured: bool) -> std::result::Result<(), &'static str> {
+        if self.user.is_none() && self.device.is_none() {
+            return Err("user or device required");
+        }
+        if auth_configured && self.user.is_none() {
+            return Err("user required");
+        }
+        if let (Some(from), Some(to)) = (self.from, self.to)
+            && from > to
+        {
+            return Err("from must not be after to");
+        }
+        // Segments such as `_audit` hold server data, not user data.
+        let reserved = |name: &Option<String>| name.as_deref().is_some_and(s3::is_reserved_segment);
+        if reserved(&self.user) || reserved(&self.device) {
+            return Err("reserved name");
+        }
+        Ok(())
+    }
+
+    // Key prefixes to list: the user's prefix, or a device's day files and rollup tier.
+    fn prefixes(&self, state: &AppState) -> Vec<String> {
+        let user_prefix = s3::user_prefix(&state.prefix, self.user.as_deref());
+        match &self.device {
+            Some(device) => vec![
+                s3::device_prefix(&state.prefix, self.user.as_deref(), device),
+                format!(
+                    "{user_prefix}{}/{}/",
+                    s3::ROLLUP_DIR,
+                    s3::sanitize_path_segment(device)
+                ),
+            ],
+            None => vec![user_prefix],
+        }
+    }
+
+    fn has_range(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+
+    fn in_range(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+
+    // Whether a key (relative to `prefix`) is covered. A device target only covers the
+    // device's own files; date ranges only cover day files and their summaries.
+    fn covers(&self, relative: &str) -> bool {
+        let file = match (&self.device, relative.split_once('/')) {
+            (Some(_), None) => relative,
+            (Some(_), Some(_)) => return false,
+            (None, _) if !self.has_range() => return true,
+            (None, Some(_)) => {
+                // `<device>/<date>.json`, or `_rollup/<device>/<date>.json` in the rollup tier.
+                let relative = relative
+                    .strip_prefix(s3::ROLLUP_DIR)
+                    .and_then(|r| r.strip_prefix('/'))
+                    .unwrap_or(relative);
+                match relative.split_once('/') {
+                    Some((_, file)) if !file.contains('/') => file,
+                    _ => return false,
+                }
+            }
+            (None, None) => return false,
+        };
+        if !self.has_range() {
+            return true;
+        }
+        s3::parse_dated_file(file).is_some_and(|date| self.in_range(date))
+    }
+}
+
+/// List, and unless `dry_run` delete, the objects covered by `erasure`, then record
+/// an audit entry under `prefix/_audit/`, also when nothing matched. A real erasure
+/// waits for running maintenance jobs, so none of them writes back what it deletes.
+#[instrument(skip(state))]
+pub async fn erase(state: &AppState, erasure: &Erasure, actor: &str) -> Result<ErasureReport> {
+    let _guard = if erasure.dry_run {
+        None
+    } else {
+        Some(maintenance::lock().await)
+    };
+    let prefixes = erasure.prefixes(state);
+    let mut objects = Vec::new();
+    for prefix in &prefixes {
+        objects.extend(
+            s3::list_objects(state, prefix, None)
+                .await?
+                .into_iter()
+                .filter(|obj| {
+                    obj.key
+                        .strip_prefix(prefix)
+                        .is_some_and(|relative| erasure.covers(relative))
+                })
+                .map(|obj| ErasedObject {
+                    key: obj.key,
+                    size: obj.size,
+                }),
+        );
+    }
+    let mut report = ErasureReport {
+        dry_run: erasure.dry_run,
+        bytes: objects.iter().map(|o| o.size).sum(),
+        objects,
+        failed: Vec::new(),
+        audit_key: None,
+    };
+    if erasure.dry_run {
+        info!(
+            ?prefixes,
+            objects = report.objects.len(),
+            "dry run, nothing erased"
+        );
+        return Ok(report);
+    }
+
+    let keys: Vec<String> = report.objects.iter().map(|o| o.key.clone()).collect();
+    if !keys.is_empty() {
+        report.failed = s3::delete_objects(state, &keys).await?;
+    }
+
+    let at = Utc::now();
+    let audit_key = format!(
+        "{}{AUDIT_DIR}/{}.json",
+        state.prefix.as_deref().unwrap_or_default(),
+        at.format("%Y-%m-%dT%H-%M-%S%.9fZ")
+    );
+    let entry = AuditEntry {
+        at,
+        actor,
+        user: erasure.user.as_deref(),
+        device: erasure.device.as_deref(),
+        from: erasure.from,
+        to: erasure.to,
+        deleted: report
+            .objects
+            .iter()
+            .filter(|o| !report.failed.contains(&o.key))
+            .collect(),
+        bytes: report.bytes,
+        failed: &report.failed,
+    };
+    s3::put_json(state, &audit_key, &entry).await?;
+    info!(
+        event = "data_erased",
+        %actor,
+        user = ?erasure.user,
+        device = ?erasure.device,
+        objects = keys.len() - report.failed.len(),
+        failed = report.failed.len(),
+        %audit_key,
+        "erased stored data"
+    );
+    report.audit_key = Some(audit_key);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn erasure(device: Option<&str>, range: Option<(&str, &str)>) -> Erasure {
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        Erasure {
+            user: Some("alice".to_string()),
+            device: device.map(str::to_string),
+            from: range.map(|(from, _)| date(from)),
+            to: range.map(|(_, to)| date(to)),
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn user_without_range_covers_everything() {
+        let all = erasure(None, None);
+        assert!(all.covers("phone/2026-09-01.json"));
+        assert!(all.covers("phone/2026-09-01.summary.json"));
+        assert!(all.covers("_rollup/phone/2026-01-01.json"));
+        assert!(all.covers("notes.txt"));
+    }
+
+    #[test]
+    fn user_range_covers_day_files_and_summaries_of_both_tiers() {
+        let range = erasure(None, Some(("2026-09-01", "2026-09-02")));
+        assert!(range.covers("phone/2026-09-01.json"));
+        assert!(range.covers("phone/2026-09-02.summary.json"));
+        assert!(range.covers("_rollup/phone/2026-09-02.json"));
+        assert!(!range.covers("_rollup/phone/2026-08-31.json"));
+        assert!(!range.covers("phone/2026-09-03.json"));
+        assert!(!range.covers("phone/notes.txt"));
+        assert!(!range.covers("2026-09-01.json"));
+    }
+
+    #[test]
+    fn device_covers_only_its_own_files() {
+        let device = erasure(Some("phone"), None);
+        assert!(device.covers("2026-09-01.json"));
+        assert!(!device.covers("nested/2026-09-01.json"));
+
+        let range = erasure(Some("phone"), Some(("2026-09-01", "2026-09-01")));
+        assert!(range.covers("2026-09-01.summary.json"));
+        assert!(!range.covers("2026-09-02.json"));
+    }
+
+    #[test]
+    fn validation_requires_a_user_once_auth_is_configured() {
+        let device_only = Erasure {
+            user: None,
+            ..erasure(Some("phone"), None)
+        };
+        assert_eq!(device_only.validate(false), Ok(()));
+        assert_eq!(device_only.validate(true), Err("user required"));
+
+        let nothing = Erasure {
+            device: None,
+            ..device_only
+        };
+        assert_eq!(nothing.validate(false), Err("user or device required"));
+    }
+
+    #[test]
+    fn validation_rejects_bad_ranges_and_reserved_names() {
+        let backwards = erasure(None, Some(("2026-09-02", "2026-09-01")));
+        assert_eq!(backwards.validate(true), Err("from must not be after to"));
+        assert_eq!(
+            erasure(Some("_audit"), None).validate(true),
+            Err("reserved name")
+        );
+    }
+}
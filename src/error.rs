@@ -29,12 +29,70 @@ pub enum Error {
         #[from] Box<aws_sdk_s3::error::SdkError<aws_sdk_s3::operation::put_object::PutObjectError>>,
     ),
 
+    #[error("s3 list error: {0}")]
+    S3List(
+        #[from]
+        Box<
+            aws_sdk_s3::error::SdkError<aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error>,
+        >,
+    ),
+
+    #[error("s3 delete error: {0}")]
+    S3Delete(
+        #[from]
+        Box<
+            aws_sdk_s3::error::SdkError<aws_sdk_s3::operation::delete_objects::DeleteObjectsError>,
+        >,
+    ),
+
+    #[error("s3 request build error: {0}")]
+    S3Build(#[from] aws_sdk_s3::error::BuildError),
+
     #[error("s3 head bucket error: {0}")]
     S3Head(
         #[from]
         Box<aws_sdk_s3::error::SdkError<aws_sdk_s3::operation::head_bucket::HeadBucketError>>,
     ),
 
+    #[error("zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("xml error: {0}")]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("xml attribute error: {0}")]
+    XmlAttr(#[from] quick_xml::events::attributes::AttrError),
+
+    #[error("request body error: {0}")]
+    Body(#[from] axum::Error),
+
+    #[error("task join error: {0}")]
+    Join(#[from] tokio::task::JoinError),
+
+    #[error("configuration error: {0}")]
+    Config(String),
+
+    #[error("password hash error: {0}")]
+    PasswordHash(String),
+
+    #[error("jwt error: {0}")]
+    Jwt(String),
+
+    #[error("tls error: {0}")]
+    Tls(String),
+
+    #[error("import error: {0}")]
+    Import(String),
+
+    #[error("upload exceeds {0} bytes")]
+    UploadTooLarge(u64),
+
+    #[error("migration error: {0}")]
+    Migration(String),
+
+    #[error("encryption error: {0}")]
+    Encryption(String),
+
     #[error("ExporterBuildError error: {source}")]
     ExporterBuild {
         #[from]
@@ -0,0 +1,292 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use chrono::{Datelike, NaiveDate, Timelike, Utc};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, instrument};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::error::Result;
+use crate::s3::{self, StoredObject};
+use crate::state::AppState;
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Which day files of a user go into an export.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportQuery {
+    pub device: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// A day file selected for export.
+#[derive(Debug)]
+pub struct ExportFile {
+    /// Path inside the archive: `<device>/<YYYY-MM-DD>.json`, or
+    /// `_rollup/<device>/<YYYY-MM-DD>.json` for the rollup tier.
+    pub path: String,
+    pub object: StoredObject,
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    generated_at: chrono::DateTime<Utc>,
+    user: Option<&'a str>,
+    device: Option<&'a str>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    path: String,
+    key: String,
+    size: usize,
+    sha256: String,
+    last_modified: Option<String>,
+}
+
+/// List the day files to export from the bucket, with their rollup tier files.
+/// `allowed` filters devices by their stored (sanitized) name, e.g. for credentials
+/// pinned to devices.
+#[instrument(skip(state, allowed))]
+pub async fn select_files(
+    state: &AppState,
+    user: Option<&str>,
+    query: &ExportQuery,
+    allowed: impl Fn(&str) -> bool,
+) -> Result<Vec<ExportFile>> {
+    let prefix = s3::user_prefix(&state.prefix, user);
+    let device = query.device.as_deref().map(s3::sanitize_path_segment);
+    let list_prefixes = match &device {
+        Some(device) => vec![
+            format!("{prefix}{device}/"),
+            format!("{prefix}{}/{device}/", s3::ROLLUP_DIR),
+        ],
+        None => vec![prefix.clone()],
+    };
+    let mut files = Vec::new();
+    for list_prefix in list_prefixes {
+        files.extend(
+            s3::list_objects(state, &list_prefix, None)
+                .await?
+                .into_iter()
+                .filter_map(|object| {
+                    let path = archive_path(object.key.strip_prefix(&prefix)?, query, &allowed)?;
+                    Some(ExportFile { path, object })
+                }),
+        );
+    }
+    debug!(files = files.len(), "selected day files for export");
+    Ok(files)
+}
+
+// Path inside the archive of a key relative to the user prefix, or `None` if it is
+// not a day or rollup tier file selected by `query`.
+fn archive_path(
+    relative: &str,
+    query: &ExportQuery,
+    allowed: impl Fn(&str) -> bool,
+) -> Option<String> {
+    let (dev, date, path) = match s3::parse_day_key(relative) {
+        Some((dev, date)) => (dev, date, format!("{dev}/{date}.json")),
+        None => {
+            let (dev, date) = s3::parse_rollup_key(relative)?;
+            (dev, date, format!("{}/{dev}/{date}.json", s3::ROLLUP_DIR))
+        }
+    };
+    let in_range =
+        query.from.is_none_or(|from| date >= from) && query.to.is_none_or(|to| date <= to);
+    (in_range && allowed(dev)).then_some(path)
+}
+
+// Collects the zip writer's output until it is handed to the response body.
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    fn take(&self) -> Bytes {
+        Bytes::from(std::mem::take(
+            &mut *self.0.lock().expect("export buffer lock poisoned"),
+        ))
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .expect("export buffer lock poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stream a zip archive of `files` followed by `manifest.json`. Day files are fetched
+/// one at a time and each is handed to the stream once compressed, so neither the
+/// archive nor the whole selection is held in memory.
+pub fn stream_zip(
+    state: AppState,
+    user: Option<String>,
+    query: ExportQuery,
+    files: Vec<ExportFile>,
+) -> impl Stream<Item = Result<Bytes>> + Send + 'static {
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        if let Err(err) = write_zip(&state, user.as_deref(), &query, files, &tx).await {
+            error!(error = ?err, "export failed");
+            let _ = tx.send(Err(err)).await;
+        }
+    });
+    futures_util::stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((chunk, rx))
+    })
+}
+
+async fn write_zip(
+    state: &AppState,
+    user: Option<&str>,
+    query: &ExportQuery,
+    files: Vec<ExportFile>,
+    tx: &mpsc::Sender<Result<Bytes>>,
+) -> Result<()> {
+    let generated_at = Utc::now();
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(zip_time(generated_at));
+    let buf = SharedBuf::default();
+    let mut zip = ZipWriter::new_stream(buf.clone());
+    let mut entries = Vec::with_capacity(files.len());
+
+    for file in files {
+        // Objects deleted since the listing are left out.
+        let Some(data) = s3::load_bytes(state, &file.object.key).await? else {
+            continue;
+        };
+        zip.start_file(file.path.as_str(), options)?;
+        zip.write_all(&data)?;
+        entries.push(ManifestEntry {
+            path: file.path,
+            key: file.object.key,
+            size: data.len(),
+            sha256: hex::encode(Sha256::digest(&data)),
+            last_modified: file.object.last_modified,
+        });
+        if tx.send(Ok(buf.take())).await.is_err() {
+            debug!("export client went away");
+            return Ok(());
+        }
+    }
+
+    let manifest = Manifest {
+        generated_at,
+        user,
+        device: query.device.as_deref(),
+        from: query.from,
+        to: query.to,
+        files: entries,
+    };
+    zip.start_file(MANIFEST_NAME, options)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+    zip.finish()?;
+    info!(files = manifest.files.len(), "export written");
+    let _ = tx.send(Ok(buf.take())).await;
+    Ok(())
+}
+
+fn zip_time(at: chrono::DateTime<Utc>) -> zip::DateTime {
+    let (year, month, day) = (at.year(), at.month(), at.day());
+    u16::try_from(year)
+        .ok()
+        .and_then(|year| {
+            zip::DateTime::from_date_and_time(
+                year,
+                month as u8,
+                day as u8,
+                at.hour() as u8,
+                at.minute() as u8,
+                at.second() as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(from: Option<&str>, to: Option<&str>) -> ExportQuery {
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        ExportQuery {
+            device: None,
+            from: from.map(date),
+            to: to.map(date),
+        }
+    }
+
+    #[test]
+    fn selects_day_files_in_range() {
+        let range = query(Some("2026-09-01"), Some("2026-09-02"));
+        assert_eq!(
+            archive_path("watch/2026-09-02.json", &range, |_| true).as_deref(),
+            Some("watch/2026-09-02.json")
+        );
+        assert_eq!(
+            archive_path("watch/2026-09-03.json", &range, |_| true),
+            None
+        );
+        assert_eq!(
+            archive_path("watch/2026-09-01.summary.json", &range, |_| true),
+            None
+        );
+        assert_eq!(
+            archive_path("_rollup/watch/2026-09-01.json", &range, |_| true).as_deref(),
+            Some("_rollup/watch/2026-09-01.json")
+        );
+        assert_eq!(archive_path("notes.json", &range, |_| true), None);
+    }
+
+    #[test]
+    fn leaves_out_devices_not_allowed() {
+        let all = query(None, None);
+        let only_watch = |device: &str| device == "watch";
+        assert!(archive_path("watch/2026-09-01.json", &all, only_watch).is_some());
+        assert!(archive_path("phone/2026-09-01.json", &all, only_watch).is_none());
+    }
+
+    #[test]
+    fn zip_time_keeps_the_timestamp_and_falls_back_outside_the_zip_range() {
+        let at = chrono::DateTime::parse_from_rfc3339("2026-09-01T10:20:30Z")
+            .unwrap()
+            .to_utc();
+        let time = zip_time(at);
+        assert_eq!((time.year(), time.month(), time.day()), (2026, 9, 1));
+        assert_eq!((time.hour(), time.minute(), time.second()), (10, 20, 30));
+
+        let ancient = chrono::DateTime::parse_from_rfc3339("1970-01-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(zip_time(ancient), zip::DateTime::default());
+    }
+
+    #[test]
+    fn shared_buffer_hands_out_what_was_written_once() {
+        let mut buf = SharedBuf::default();
+        buf.write_all(b"abc").unwrap();
+        assert_eq!(buf.clone().take(), Bytes::from_static(b"abc"));
+        assert!(buf.take().is_empty());
+    }
+}
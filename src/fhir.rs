@@ -0,0 +1,253 @@
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Value as JsonValue, json};
+
+use crate::items::{self, BLOOD_PRESSURE_DIASTOLIC, BLOOD_PRESSURE_SYSTOLIC, Reading, Sample};
+
+const LOINC_SYSTEM: &str = "http://loinc.org";
+const UCUM_SYSTEM: &str = "http://unitsofmeasure.org";
+const CATEGORY_SYSTEM: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+
+// Blood pressure panel wrapping the systolic/diastolic components.
+const BLOOD_PRESSURE_PANEL: &str = "85354-9";
+
+struct Code {
+    loinc: &'static str,
+    display: &'static str,
+    category: &'static str,
+    ucum: &'static str,
+    unit: &'static str,
+}
+
+// LOINC codes for the metrics we know how to express as Observations.
+fn code_for(metric: &str) -> Option<Code> {
+    let code = match metric {
+        "heart_rate" => Code {
+            loinc: "8867-4",
+            display: "Heart rate",
+            category: "vital-signs",
+            ucum: "/min",
+            unit: "beats/minute",
+        },
+        "step_count" => Code {
+            loinc: "55423-8",
+            display: "Number of steps",
+            category: "activity",
+            ucum: "{steps}",
+            unit: "steps",
+        },
+        "oxygen_saturation" => Code {
+            loinc: "59408-5",
+            display: "Oxygen saturation in Arterial blood by Pulse oximetry",
+            category: "vital-signs",
+            ucum: "%",
+            unit: "%",
+        },
+        "body_mass" => Code {
+            loinc: "29463-7",
+            display: "Body weight",
+            category: "vital-signs",
+            ucum: "kg",
+            unit: "kg",
+        },
+        BLOOD_PRESSURE_SYSTOLIC => Code {
+            loinc: "8480-6",
+            display: "Systolic blood pressure",
+            category: "vital-signs",
+            ucum: "mm[Hg]",
+            unit: "mmHg",
+        },
+        BLOOD_PRESSURE_DIASTOLIC => Code {
+            loinc: "8462-4",
+            display: "Diastolic blood pressure",
+            category: "vital-signs",
+            ucum: "mm[Hg]",
+            unit: "mmHg",
+        },
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Wrap the Observations of all `items` into a FHIR R4 `collection` Bundle.
+pub fn bundle(device_name: &str, items: &[JsonValue]) -> JsonValue {
+    let entries: Vec<JsonValue> = items
+        .iter()
+        .flat_map(|item| observations(device_name, item))
+        .map(|resource| json!({ "resource": resource }))
+        .collect();
+    json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "entry": entries,
+    })
+}
+
+/// Convert one stored item into Observations; metrics without a LOINC mapping are dropped.
+///
+/// Systolic and diastolic readings taken at the same time become a single
+/// blood pressure panel with two components.
+pub fn observations(device_name: &str, item: &JsonValue) -> Vec<JsonValue> {
+    items::readings(item)
+        .iter()
+        .filter_map(|reading| match reading {
+            Reading::Single(sample) => observation(device_name, sample),
+            Reading::BloodPressure {
+                systolic,
+                diastolic,
+            } => Some(blood_pressure_panel(device_name, systolic, diastolic)),
+        })
+        .collect()
+}
+
+fn observation(device_name: &str, sample: &Sample) -> Option<JsonValue> {
+    let code = code_for(&sample.metric)?;
+    let mut obs = base_observation(device_name, sample, &code);
+    obs["valueQuantity"] = quantity(sample, &code)?;
+    Some(obs)
+}
+
+fn blood_pressure_panel(device_name: &str, systolic: &Sample, diastolic: &Sample) -> JsonValue {
+    let panel = Code {
+        loinc: BLOOD_PRESSURE_PANEL,
+        display: "Blood pressure panel with all children optional",
+        category: "vital-signs",
+        ucum: "mm[Hg]",
+        unit: "mmHg",
+    };
+    let mut obs = base_observation(device_name, systolic, &panel);
+    obs["component"] = [systolic, diastolic]
+        .iter()
+        .filter_map(|s| {
+            let code = code_for(&s.metric)?;
+            Some(json!({
+                "code": codeable_concept(&code),
+                "valueQuantity": quantity(s, &code)?,
+            }))
+        })
+        .collect();
+    obs
+}
+
+fn base_observation(device_name: &str, sample: &Sample, code: &Code) -> JsonValue {
+    let mut obs = json!({
+        "resourceType": "Observation",
+        "status": "final",
+        "category": [{
+            "coding": [{ "system": CATEGORY_SYSTEM, "code": code.category }],
+        }],
+        "code": codeable_concept(code),
+        "device": { "display": device_name },
+    });
+    let start = sample.start.to_rfc3339_opts(SecondsFormat::Secs, true);
+    match sample.end.filter(|end| *end != sample.start) {
+        Some(end) => {
+            obs["effectivePeriod"] = json!({
+                "start": start,
+                "end": end.to_rfc3339_opts(SecondsFormat::Secs, true),
+            });
+        }
+        None => obs["effectiveDateTime"] = json!(start),
+    }
+    obs
+}
+
+fn codeable_concept(code: &Code) -> JsonValue {
+    json!({
+        "coding": [{ "system": LOINC_SYSTEM, "code": code.loinc, "display": code.display }],
+        "text": code.display,
+    })
+}
+
+fn quantity(sample: &Sample, code: &Code) -> Option<JsonValue> {
+    Some(json!({
+        "value": items::standard_value(sample)?,
+        "unit": code.unit,
+        "system": UCUM_SYSTEM,
+        "code": code.ucum,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_known_metrics_to_loinc_observations() {
+        let item = json!({
+            "type": "HKQuantityTypeIdentifierHeartRate",
+            "value": 61.0,
+            "unit": "count/min",
+            "startDate": "2026-09-01 08:00:00 +0000",
+            "endDate": "2026-09-01 08:00:00 +0000",
+        });
+        let obs = observations("Watch", &item);
+        assert_eq!(obs.len(), 1);
+        assert_eq!(obs[0]["code"]["coding"][0]["code"], "8867-4");
+        assert_eq!(obs[0]["category"][0]["coding"][0]["code"], "vital-signs");
+        assert_eq!(obs[0]["valueQuantity"]["value"], 61.0);
+        assert_eq!(obs[0]["valueQuantity"]["code"], "/min");
+        assert_eq!(obs[0]["device"]["display"], "Watch");
+        // Instantaneous samples get a date time rather than a period.
+        assert_eq!(obs[0]["effectiveDateTime"], "2026-09-01T08:00:00Z");
+
+        let unknown = json!({ "type": "Flights", "value": 3, "startDate": "2026-09-01" });
+        assert!(observations("Watch", &unknown).is_empty());
+    }
+
+    #[test]
+    fn converts_units_and_keeps_periods() {
+        let item = json!({
+            "name": "body_mass",
+            "units": "lb",
+            "data": [{ "date": "2026-09-01 08:00:00 +0000", "qty": 150 }],
+        });
+        let obs = observations("Scale", &item);
+        let kg = obs[0]["valueQuantity"]["value"].as_f64().unwrap();
+        assert!((kg - 68.038_855_5).abs() < 1e-6);
+
+        let steps = json!({
+            "type": "HKQuantityTypeIdentifierStepCount",
+            "value": 120,
+            "startDate": "2026-09-01 08:00:00 +0000",
+            "endDate": "2026-09-01 08:10:00 +0000",
+        });
+        let obs = observations("Phone", &steps);
+        assert_eq!(obs[0]["effectivePeriod"]["end"], "2026-09-01T08:10:00Z");
+
+        let saturation =
+            json!({ "type": "OxygenSaturation", "value": 0.97, "startDate": "2026-09-01" });
+        assert_eq!(
+            observations("Watch", &saturation)[0]["valueQuantity"]["value"],
+            97.0
+        );
+
+        let unknown =
+            json!({ "type": "BodyMass", "value": 11, "unit": "slug", "startDate": "2026-09-01" });
+        assert!(observations("Scale", &unknown).is_empty());
+    }
+
+    #[test]
+    fn pairs_blood_pressure_readings_into_a_panel() {
+        let item = json!({
+            "name": "blood_pressure",
+            "units": "mmHg",
+            "data": [
+                { "date": "2026-09-01 08:00:00 +0000", "systolic": 120, "diastolic": 80 },
+                { "date": "2026-09-01 09:00:00 +0000", "systolic": 125 },
+            ],
+        });
+        let obs = observations("Cuff", &item);
+        assert_eq!(obs.len(), 2);
+        assert_eq!(obs[0]["code"]["coding"][0]["code"], BLOOD_PRESSURE_PANEL);
+        let components = obs[0]["component"].as_array().unwrap();
+        assert_eq!(components[0]["code"]["coding"][0]["code"], "8480-6");
+        assert_eq!(components[1]["valueQuantity"]["value"], 80.0);
+        // An unpaired reading stays a plain observation.
+        assert_eq!(obs[1]["code"]["coding"][0]["code"], "8480-6");
+
+        let bundle = bundle("Cuff", &[item]);
+        assert_eq!(bundle["resourceType"], "Bundle");
+        assert_eq!(bundle["entry"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,225 @@
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use serde_json::{Value as JsonValue, json};
+use std::io::BufRead;
+
+use tracing::debug;
+
+use crate::error::Result;
+
+#[derive(Default)]
+struct TrackPoint {
+    lon: f64,
+    lat: f64,
+    ele: Option<f64>,
+    time: Option<String>,
+}
+
+/// Convert a GPX document into a GeoJSON `Feature`.
+///
+/// Each `<trkseg>` becomes one line; a single segment yields a `LineString`, several
+/// yield a `MultiLineString`. Point timestamps are kept in the `coordTimes` property.
+pub fn to_feature<R: BufRead>(input: R, source: &str) -> Result<JsonValue> {
+    let mut reader = Reader::from_reader(input);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut name: Option<String> = None;
+    let mut segments: Vec<Vec<TrackPoint>> = Vec::new();
+    let mut point: Option<TrackPoint> = None;
+    // Innermost open element, used to route text content.
+    let mut current = String::new();
+    let mut in_trk = false;
+    let mut in_trkpt = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                current = element_name(&e);
+                match current.as_str() {
+                    "trk" => in_trk = true,
+                    "trkseg" => segments.push(Vec::new()),
+                    "trkpt" => {
+                        in_trkpt = true;
+                        point = track_point(&e)?;
+                    }
+                    _ => {}
+                }
+            }
+            Event::Empty(e) if element_name(&e) == "trkpt" => {
+                if let Some(p) = track_point(&e)? {
+                    push_point(&mut segments, p);
+                }
+            }
+            Event::Text(t) => {
+                let text = String::from_utf8_lossy(&t).into_owned();
+                match (current.as_str(), point.as_mut()) {
+                    ("ele", Some(p)) => p.ele = text.parse().ok(),
+                    ("time", Some(p)) => p.time = Some(text),
+                    ("name", None) if in_trk && !in_trkpt && name.is_none() => name = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                match e.local_name().as_ref() {
+                    b"trkpt" => {
+                        in_trkpt = false;
+                        if let Some(p) = point.take() {
+                            push_point(&mut segments, p);
+                        }
+                    }
+                    b"trk" => in_trk = false,
+                    _ => {}
+                }
+                current.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    segments.retain(|s| !s.is_empty());
+    let times: Vec<Vec<JsonValue>> = segments
+        .iter()
+        .map(|s| s.iter().map(|p| json!(p.time)).collect())
+        .collect();
+    let lines: Vec<Vec<JsonValue>> = segments
+        .iter()
+        .map(|s| s.iter().map(coordinate).collect())
+        .collect();
+
+    let (geometry, coord_times) = if lines.len() == 1 {
+        (
+            json!({ "type": "LineString", "coordinates": lines[0] }),
+            json!(times[0]),
+        )
+    } else {
+        (
+            json!({ "type": "MultiLineString", "coordinates": lines }),
+            json!(times),
+        )
+    };
+
+    Ok(json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": {
+            "name": name,
+            "source": source,
+            "coordTimes": coord_times,
+        },
+    }))
+}
+
+fn element_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.local_name().as_ref()).into_owned()
+}
+
+// A point with both coordinates; points missing either one, or with one out of range,
+// are dropped rather than drawn at (0, 0).
+fn track_point(e: &BytesStart) -> Result<Option<TrackPoint>> {
+    let (mut lat, mut lon) = (None, None);
+    for attr in e.attributes() {
+        let attr = attr?;
+        let value = String::from_utf8_lossy(&attr.value);
+        let degrees = value.trim().parse::<f64>().ok();
+        match attr.key.as_ref() {
+            b"lat" => lat = degrees.filter(|d| d.abs() <= 90.0),
+            b"lon" => lon = degrees.filter(|d| d.abs() <= 180.0),
+            _ => {}
+        }
+    }
+    match (lat, lon) {
+        (Some(lat), Some(lon)) => Ok(Some(TrackPoint {
+            lat,
+            lon,
+            ..TrackPoint::default()
+        })),
+        _ => {
+            debug!(?lat, ?lon, "skipping track point without valid coordinates");
+            Ok(None)
+        }
+    }
+}
+
+fn push_point(segments: &mut Vec<Vec<TrackPoint>>, p: TrackPoint) {
+    match segments.last_mut() {
+        Some(segment) => segment.push(p),
+        None => segments.push(vec![p]),
+    }
+}
+
+// GeoJSON positions are [longitude, latitude, elevation?].
+fn coordinate(p: &TrackPoint) -> JsonValue {
+    match p.ele {
+        Some(ele) => json!([p.lon, p.lat, ele]),
+        None => json!([p.lon, p.lat]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(gpx: &str) -> JsonValue {
+        to_feature(gpx.as_bytes(), "route.gpx").unwrap()
+    }
+
+    #[test]
+    fn single_segment_becomes_a_line_string() {
+        let f = feature(
+            r#"<gpx><trk><name>Morning Run</name><trkseg>
+              <trkpt lat="52.5" lon="13.4"><ele>34.5</ele><time>2026-09-01T06:00:00Z</time></trkpt>
+              <trkpt lat="52.6" lon="13.5"/>
+            </trkseg></trk></gpx>"#,
+        );
+        assert_eq!(f["geometry"]["type"], "LineString");
+        assert_eq!(
+            f["geometry"]["coordinates"],
+            json!([[13.4, 52.5, 34.5], [13.5, 52.6]])
+        );
+        assert_eq!(f["properties"]["name"], "Morning Run");
+        assert_eq!(f["properties"]["source"], "route.gpx");
+        assert_eq!(
+            f["properties"]["coordTimes"],
+            json!(["2026-09-01T06:00:00Z", null])
+        );
+    }
+
+    #[test]
+    fn several_segments_become_a_multi_line_string() {
+        let f = feature(
+            r#"<gpx><trk>
+              <trkseg><trkpt lat="1" lon="2"/></trkseg>
+              <trkseg></trkseg>
+              <trkseg><trkpt lat="3" lon="4"/></trkseg>
+            </trk></gpx>"#,
+        );
+        assert_eq!(f["geometry"]["type"], "MultiLineString");
+        assert_eq!(
+            f["geometry"]["coordinates"],
+            json!([[[2.0, 1.0]], [[4.0, 3.0]]])
+        );
+        assert_eq!(f["properties"]["name"], JsonValue::Null);
+    }
+
+    #[test]
+    fn points_without_valid_coordinates_are_dropped() {
+        let f = feature(
+            r#"<gpx><trk><name>Walk</name><trkseg>
+              <trkpt lat="10" lon="20"/>
+              <trkpt lon="21"><name>no latitude</name><ele>5</ele></trkpt>
+              <trkpt lat="north" lon="22"/>
+              <trkpt lat="95" lon="23"/>
+              <trkpt lat="11" lon="24"/>
+            </trkseg></trk></gpx>"#,
+        );
+        assert_eq!(
+            f["geometry"]["coordinates"],
+            json!([[20.0, 10.0], [24.0, 11.0]])
+        );
+        assert_eq!(f["properties"]["name"], "Walk");
+        assert_eq!(f["properties"]["coordTimes"], json!([null, null]));
+    }
+}
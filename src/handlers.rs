@@ -1,15 +1,30 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
 use axum::{
-    Json,
-    extract::State,
-    http::{Method, StatusCode},
-    response::IntoResponse,
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    response::{IntoResponse, Response},
 };
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta, Utc};
+use futures_util::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use tracing::{debug, instrument};
+use tracing::{Span, debug, error, info, instrument};
 
+use crate::aggregate::{Aggregator, Interval, Series};
+use crate::apple_export::{self, ImportOptions, ImportStatus};
+use crate::auth::AuthUser;
+use crate::erasure::{self, Erasure};
+use crate::error::Error;
+use crate::export::{self, ExportQuery};
+use crate::fhir;
+use crate::items;
 use crate::metrics;
-use crate::s3::IngestJob;
+use crate::omh;
+use crate::s3::{self, IngestJob};
 use crate::state::AppState;
 
 #[instrument(skip_all)]
@@ -18,37 +33,107 @@ pub async fn health() -> impl IntoResponse {
     (StatusCode::OK, "ok")
 }
 
+// Resolve the caller's user prefix, rejecting devices its API key is not pinned to.
+// Pinned keys must name the device explicitly, and device names may not fall in
+// the reserved `_` namespace.
+fn scoped_user(
+    user: Option<Extension<AuthUser>>,
+    device_name: Option<&str>,
+) -> Result<Option<String>, (StatusCode, &'static str)> {
+    if let Some(device) = device_name
+        && s3::is_reserved_segment(device)
+    {
+        debug!(%device, "reserved device name");
+        return Err((StatusCode::BAD_REQUEST, "reserved device name"));
+    }
+    let Some(Extension(user)) = user else {
+        return Ok(None);
+    };
+    match device_name {
+        Some(device) if user.allows_device(device) => Ok(Some(user.name)),
+        None if user.devices.is_none() => Ok(Some(user.name)),
+        _ => {
+            debug!(user = %user.name, device = ?device_name, "device not allowed for credential");
+            Err((StatusCode::FORBIDDEN, "device not allowed"))
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct IngestRequest {
     pub device_name: String,
     pub data: Vec<JsonValue>,
 }
 
-#[instrument(
-    skip(state, payload, method),
-    fields(
-        http_method = %method,
-        device_name = %payload.device_name,
-        items = %payload.data.len()
+// 429 with a Retry-After header rounded up to whole seconds.
+fn too_many_requests(retry_after: Duration, message: &'static str) -> Response {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, secs.max(1).to_string())],
+        message,
     )
+        .into_response()
+}
+
+#[instrument(
+    skip(state, user, client, body, method),
+    fields(http_method = %method, device_name, items)
 )]
 pub async fn ingest(
     method: Method,
     State(state): State<AppState>,
-    Json(payload): Json<IngestRequest>,
-) -> impl IntoResponse {
+    user: Option<Extension<AuthUser>>,
+    client: Option<Extension<ConnectInfo<SocketAddr>>>,
+    body: Bytes,
+) -> Response {
+    // Limits are keyed by the authenticated user, or the client IP without auth
+    let client_key = match (&user, &client) {
+        (Some(Extension(user)), _) => format!("user:{}", user.name),
+        (None, Some(Extension(ConnectInfo(addr)))) => format!("ip:{}", addr.ip()),
+        (None, None) => "anonymous".to_string(),
+    };
+    if let Some(limiter) = &state.rate_limiter
+        && let Err(retry_after) = limiter.check(&client_key)
+    {
+        debug!(client = %client_key, ?retry_after, "rate limit exceeded");
+        metrics::inc_ingest_rejected("rate_limit");
+        return too_many_requests(retry_after, "rate limited");
+    }
+
+    let Json(payload) = match Json::<IngestRequest>::from_bytes(&body) {
+        Ok(payload) => payload,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let span = Span::current();
+    span.record("device_name", payload.device_name.as_str());
+    span.record("items", payload.data.len());
+
     // Metrics: count incoming requests to /ingest by method and device
     metrics::inc_ingest_request(method.as_str(), Some(&payload.device_name));
+    let user = match scoped_user(user, Some(&payload.device_name)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Some(quotas) = &state.quotas
+        && let Err(retry_after) =
+            quotas.charge(&client_key, body.len() as u64, payload.data.len() as u64)
+    {
+        debug!(client = %client_key, ?retry_after, "daily quota exceeded");
+        metrics::inc_ingest_rejected("quota");
+        return too_many_requests(retry_after, "quota exceeded");
+    }
     debug!(device = %payload.device_name, items = payload.data.len(), "enqueueing ingest job");
     // Enqueue the job for background processing
     let job = IngestJob {
+        user,
         device_name: payload.device_name,
         payload: JsonValue::Array(payload.data),
     };
     match state.tx.try_send(job) {
         Ok(()) => {
             debug!("job queued successfully");
-            (StatusCode::ACCEPTED, "queued")
+            (StatusCode::ACCEPTED, "queued").into_response()
         }
         Err(err) => {
             use tokio::sync::mpsc::error::TrySendError;
@@ -62,7 +147,663 @@ pub async fn ingest(
                     StatusCode::INTERNAL_SERVER_ERROR
                 }
             };
-            (code, "unavailable")
+            (code, "unavailable").into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    pub device_name: Option<String>,
+}
+
+#[instrument(skip(state, user, body), fields(device_name = ?query.device_name))]
+pub async fn import_apple(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<ImportQuery>,
+    body: Body,
+) -> Response {
+    let user = match scoped_user(user, query.device_name.as_deref()) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let Ok(permit) = state.import_slots.clone().try_acquire_owned() else {
+        debug!("import concurrency limit reached");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "too many imports in progress",
+        )
+            .into_response();
+    };
+    // Spool the upload first: the zip central directory sits at the end of the file.
+    let upload = match apple_export::spool_upload(body, state.import_max_bytes).await {
+        Ok(upload) => upload,
+        Err(Error::UploadTooLarge(limit)) => {
+            debug!(limit, "Apple Health export too large");
+            return (StatusCode::PAYLOAD_TOO_LARGE, "upload too large").into_response();
+        }
+        Err(err) => {
+            error!(error = ?err, "failed to receive Apple Health export");
+            return (StatusCode::BAD_REQUEST, "invalid upload").into_response();
+        }
+    };
+    let opts = ImportOptions {
+        user,
+        device_name: query.device_name,
+    };
+    let job = state.import_jobs.start(opts.user.as_deref());
+    debug!(%job, "spawning Apple Health import");
+    let location = format!("/import/apple/{job}");
+    let accepted = Json(serde_json::json!({ "job": job, "status": "running" }));
+    tokio::spawn(async move {
+        let status = match apple_export::import_file(&state, upload.path(), opts).await {
+            Ok(summary) => {
+                info!(
+                    %job,
+                    items = summary.items,
+                    skipped = summary.skipped,
+                    reserved = summary.reserved,
+                    routes = summary.routes,
+                    writes = summary.writes,
+                    duplicates = summary.duplicates,
+                    "Apple Health import finished"
+                );
+                ImportStatus::Finished(summary)
+            }
+            Err(err) => {
+                error!(error = ?err, %job, "Apple Health import failed");
+                ImportStatus::Failed {
+                    error: err.to_string(),
+                }
+            }
+        };
+        state.import_jobs.finish(&job, status);
+        drop(upload);
+        drop(permit);
+    });
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        accepted,
+    )
+        .into_response()
+}
+
+#[instrument(skip(state, user))]
+pub async fn import_status(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Path(job): Path<String>,
+) -> Response {
+    let user = user.map(|Extension(user)| user.name);
+    match state.import_jobs.get(&job, user.as_deref()) {
+        Some(status) => Json(status).into_response(),
+        None => (StatusCode::NOT_FOUND, "unknown import").into_response(),
+    }
+}
+
+#[instrument(skip(state, user, headers))]
+pub async fn device_day(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Path((device, date)): Path<(String, NaiveDate)>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match scoped_user(user, Some(&device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let key = s3::s3_key_for_device_date(&state.prefix, user.as_deref(), &device, date);
+    match stream_object(&state, &key, &headers).await {
+        Ok(Some(response)) => response,
+        Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+#[instrument(skip(state, user, headers))]
+pub async fn device_day_summary(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Path((device, date)): Path<(String, NaiveDate)>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match scoped_user(user, Some(&device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let day_key = s3::s3_key_for_device_date(&state.prefix, user.as_deref(), &device, date);
+    match stream_object(&state, &s3::summary_key(&day_key), &headers).await {
+        Ok(Some(response)) => return response,
+        Ok(None) => {}
+        Err(rejection) => return rejection.into_response(),
+    }
+    // Day files written before summaries existed get one computed on the fly.
+    let summary = match s3::load_json(&state, &day_key).await {
+        Ok(Some(day)) => {
+            debug!(%day_key, "summarizing day file without stored summary");
+            s3::summarize_day(&state, &day_key, &day).await
+        }
+        Ok(None) => return (StatusCode::NOT_FOUND, "not found").into_response(),
+        Err(err) => Err(err),
+    };
+    match summary {
+        Ok(summary) => Json(summary).into_response(),
+        Err(err) => {
+            error!(error = ?err, %day_key, "failed to load day file");
+            (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response()
+        }
+    }
+}
+
+// Stream a stored JSON object, honouring `If-None-Match`; `None` if it does not exist.
+async fn stream_object(
+    state: &AppState,
+    key: &str,
+    headers: &HeaderMap,
+) -> Result<Option<Response>, (StatusCode, &'static str)> {
+    let if_none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let obj = match s3::fetch_object(state, key, if_none_match.clone()).await {
+        Ok(s3::Fetched::Object(obj)) => obj,
+        Ok(s3::Fetched::NotModified) => {
+            debug!(%key, "object not modified");
+            let mut response = StatusCode::NOT_MODIFIED.into_response();
+            if let Some(etag) = if_none_match.and_then(|v| HeaderValue::from_str(&v).ok()) {
+                response.headers_mut().insert(header::ETAG, etag);
+            }
+            return Ok(Some(response));
+        }
+        Ok(s3::Fetched::NotFound) => return Ok(None),
+        Err(err) => {
+            error!(error = ?err, %key, "failed to fetch object");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "storage error"));
+        }
+    };
+
+    let etag = obj.e_tag.clone();
+    // Encrypted objects are authenticated as a whole, so they are decrypted before sending.
+    let (body, len) = if s3::is_encrypted(&obj) {
+        match s3::read_object(state, key, *obj).await {
+            Ok(data) => {
+                let len = data.len() as i64;
+                (Body::from(data), Some(len))
+            }
+            Err(err) => {
+                error!(error = ?err, %key, "failed to decrypt object");
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "storage error"));
+            }
+        }
+    } else {
+        (
+            Body::from_stream(s3::body_stream(obj.body)),
+            obj.content_length,
+        )
+    };
+    let mut response = Response::new(body);
+    let out = response.headers_mut();
+    out.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    if let Some(len) = len.filter(|len| *len >= 0) {
+        out.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    }
+    if let Some(etag) = etag.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        out.insert(header::ETAG, etag);
+    }
+    Ok(Some(response))
+}
+
+/// Optional inclusive date filter for listings.
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl ListQuery {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayEntry {
+    pub date: NaiveDate,
+    pub size: i64,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceEntry {
+    pub device: String,
+    pub days: usize,
+    pub bytes: i64,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceList {
+    pub devices: Vec<DeviceEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayList {
+    pub device: String,
+    pub days: Vec<DayEntry>,
+}
+
+#[instrument(skip(state, user))]
+pub async fn list_devices(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    // Keys pinned to devices only see those devices, compared by their stored names.
+    let (user, pinned) = match user {
+        Some(Extension(user)) => (Some(user.name), user.devices),
+        None => (None, None),
+    };
+    let prefix = s3::user_prefix(&state.prefix, user.as_deref());
+    let objects = match s3::list_objects(&state, &prefix, None).await {
+        Ok(objects) => objects,
+        Err(err) => {
+            error!(error = ?err, %prefix, "failed to list objects");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response();
+        }
+    };
+
+    let mut devices: Vec<DeviceEntry> = Vec::new();
+    for obj in &objects {
+        let Some((device, date)) = obj.key.strip_prefix(&prefix).and_then(s3::parse_day_key) else {
+            continue;
+        };
+        if !query.contains(date) {
+            continue;
+        }
+        if let Some(pinned) = &pinned
+            && !pinned
+                .iter()
+                .any(|d| s3::sanitize_path_segment(d) == device)
+        {
+            continue;
+        }
+        // Listings are sorted by key, so a device's days are contiguous.
+        match devices.last_mut() {
+            Some(entry) if entry.device == device => {
+                entry.days += 1;
+                entry.bytes += obj.size;
+                entry.first_date = entry.first_date.min(date);
+                entry.last_date = entry.last_date.max(date);
+                if obj.last_modified > entry.last_modified {
+                    entry.last_modified = obj.last_modified.clone();
+                }
+            }
+            _ => devices.push(DeviceEntry {
+                device: device.to_string(),
+                days: 1,
+                bytes: obj.size,
+                first_date: date,
+                last_date: date,
+                last_modified: obj.last_modified.clone(),
+            }),
+        }
+    }
+    debug!(devices = devices.len(), "listed devices");
+    Json(DeviceList { devices }).into_response()
+}
+
+#[instrument(skip(state, user))]
+pub async fn list_device_days(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Path(device): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Response {
+    let user = match scoped_user(user, Some(&device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let prefix = s3::device_prefix(&state.prefix, user.as_deref(), &device);
+    // Day keys sort by date, so listing can start at the lower bound.
+    let start_after = query.from.map(|from| format!("{prefix}{from}"));
+    let objects = match s3::list_objects(&state, &prefix, start_after).await {
+        Ok(objects) => objects,
+        Err(err) => {
+            error!(error = ?err, %prefix, "failed to list objects");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response();
+        }
+    };
+
+    let days: Vec<DayEntry> = objects
+        .into_iter()
+        .filter_map(|obj| {
+            let date = s3::parse_day_file(obj.key.strip_prefix(&prefix)?)?;
+            query.contains(date).then_some(DayEntry {
+                date,
+                size: obj.size,
+                last_modified: obj.last_modified,
+            })
+        })
+        .collect();
+    debug!(days = days.len(), "listed device days");
+    Json(DayList { device, days }).into_response()
+}
+
+// Upper bound on the number of day files a single read request may load.
+const MAX_RANGE_DAYS: i64 = 31;
+
+#[derive(Debug, Deserialize)]
+pub struct DayRangeQuery {
+    pub device: String,
+    pub from: NaiveDate,
+    pub to: Option<NaiveDate>,
+}
+
+impl DayRangeQuery {
+    // Inclusive (from, to), rejecting inverted or oversized ranges.
+    fn bounds(&self) -> Result<(NaiveDate, NaiveDate), (StatusCode, &'static str)> {
+        let to = self.to.unwrap_or(self.from);
+        let days = (to - self.from).num_days();
+        if days < 0 {
+            return Err((StatusCode::BAD_REQUEST, "from must not be after to"));
+        }
+        if days >= MAX_RANGE_DAYS {
+            return Err((StatusCode::BAD_REQUEST, "date range too large"));
+        }
+        Ok((self.from, to))
+    }
+}
+
+#[instrument(skip(state, user))]
+pub async fn fhir_observations(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<DayRangeQuery>,
+) -> Response {
+    let (from, to) = match query.bounds() {
+        Ok(bounds) => bounds,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let user = match scoped_user(user, Some(&query.device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let items = match s3::load_day_range(&state, user.as_deref(), &query.device, from, to).await {
+        Ok(items) => items,
+        Err(err) => {
+            error!(error = ?err, "failed to load day files");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response();
+        }
+    };
+    debug!(items = items.len(), "rendering FHIR bundle");
+    let bundle = fhir::bundle(&query.device, &items);
+    (
+        [(header::CONTENT_TYPE, "application/fhir+json")],
+        Json(bundle),
+    )
+        .into_response()
+}
+
+#[instrument(skip(state, user))]
+pub async fn omh_data_points(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<DayRangeQuery>,
+) -> Response {
+    let (from, to) = match query.bounds() {
+        Ok(bounds) => bounds,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let user = match scoped_user(user, Some(&query.device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let items = match s3::load_day_range(&state, user.as_deref(), &query.device, from, to).await {
+        Ok(items) => items,
+        Err(err) => {
+            error!(error = ?err, "failed to load day files");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response();
         }
+    };
+    debug!(items = items.len(), "rendering Open mHealth data points");
+    let points: Vec<JsonValue> = items
+        .iter()
+        .flat_map(|item| omh::data_points(&query.device, item, false))
+        .collect();
+    Json(points).into_response()
+}
+
+// Widest date range /query accepts; samples are streamed, so it can exceed MAX_RANGE_DAYS.
+const MAX_QUERY_DAYS: i64 = 366;
+
+type TimeBounds = (DateTime<FixedOffset>, DateTime<FixedOffset>);
+
+// Inclusive time bounds; a bare `to` date covers that whole day, and a missing one means now.
+fn time_bounds(from: &str, to: Option<&str>) -> Result<TimeBounds, (StatusCode, &'static str)> {
+    let from = items::parse_timestamp(from).ok_or((StatusCode::BAD_REQUEST, "invalid from"))?;
+    let to = match to {
+        None => Utc::now().fixed_offset(),
+        Some(to) => {
+            let at = items::parse_timestamp(to).ok_or((StatusCode::BAD_REQUEST, "invalid to"))?;
+            if to.parse::<NaiveDate>().is_ok() {
+                at + TimeDelta::days(1) - TimeDelta::nanoseconds(1)
+            } else {
+                at
+            }
+        }
+    };
+    if to < from {
+        return Err((StatusCode::BAD_REQUEST, "from must not be after to"));
+    }
+    if (to - from).num_days() >= MAX_QUERY_DAYS {
+        return Err((StatusCode::BAD_REQUEST, "date range too large"));
     }
+    Ok((from, to))
+}
+
+// Items of every day file covering the bounds, in date order. Day files are
+// named by the phone's local date, so the day after the UTC end is included
+// too.
+fn day_items(
+    state: AppState,
+    user: Option<String>,
+    device: String,
+    (from, to): TimeBounds,
+) -> impl futures_util::Stream<Item = crate::error::Result<Vec<JsonValue>>> + Send + 'static {
+    let first = from.to_utc().date_naive();
+    let last = to.to_utc().date_naive() + chrono::Days::new(1);
+    debug!(%device, %first, %last, "loading day files");
+    s3::day_stream(state, user, device, first, last)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SampleQuery {
+    pub device: String,
+    pub metric: Option<String>,
+    pub from: String,
+    pub to: Option<String>,
+}
+
+#[instrument(skip(state, user))]
+pub async fn query_samples(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<SampleQuery>,
+) -> Response {
+    let (from, to) = match time_bounds(&query.from, query.to.as_deref()) {
+        Ok(bounds) => bounds,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let user = match scoped_user(user, Some(&query.device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let metric = query.metric.as_deref().map(items::canonical_metric);
+    debug!(?metric, "streaming samples");
+
+    let lines = day_items(state, user, query.device, (from, to))
+        .map_ok(move |day| {
+            let mut out = Vec::new();
+            for sample in day.iter().flat_map(items::samples) {
+                if metric.as_ref().is_some_and(|m| *m != sample.metric)
+                    || sample.start < from
+                    || sample.start > to
+                {
+                    continue;
+                }
+                if serde_json::to_writer(&mut out, &sample).is_ok() {
+                    out.push(b'\n');
+                }
+            }
+            Bytes::from(out)
+        })
+        .inspect_err(|err| error!(error = ?err, "failed to load day file"));
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateQuery {
+    pub device: String,
+    pub metric: String,
+    pub from: String,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub interval: Interval,
+    /// UTC offset such as `+02:00` that day and hour buckets are aligned to.
+    pub tz: Option<String>,
+}
+
+#[instrument(skip(state, user))]
+pub async fn aggregate_samples(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<AggregateQuery>,
+) -> Response {
+    let (from, to) = match time_bounds(&query.from, query.to.as_deref()) {
+        Ok(bounds) => bounds,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let offset = match query.tz.as_deref().map(str::parse::<FixedOffset>) {
+        None => FixedOffset::east_opt(0).expect("zero offset"),
+        Some(Ok(offset)) => offset,
+        Some(Err(_)) => return (StatusCode::BAD_REQUEST, "invalid tz").into_response(),
+    };
+    let user = match scoped_user(user, Some(&query.device)) {
+        Ok(user) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let metric = items::canonical_metric(&query.metric);
+
+    let mut aggregator = Aggregator::new(query.interval, offset);
+    let mut days = std::pin::pin!(day_items(state, user, query.device.clone(), (from, to)));
+    loop {
+        let day = match days.try_next().await {
+            Ok(Some(day)) => day,
+            Ok(None) => break,
+            Err(err) => {
+                error!(error = ?err, "failed to load day files");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response();
+            }
+        };
+        day.iter()
+            .flat_map(items::samples)
+            .filter(|s| s.metric == metric && s.start >= from && s.start <= to)
+            .for_each(|s| aggregator.add(&s));
+    }
+    let series = aggregator.finish();
+    debug!(buckets = series.start.len(), "aggregated samples");
+    Json(AggregateResponse {
+        device: query.device,
+        metric,
+        interval: query.interval,
+        series,
+    })
+    .into_response()
+}
+
+#[derive(Debug, Serialize)]
+pub struct AggregateResponse {
+    pub device: String,
+    pub metric: String,
+    pub interval: Interval,
+    #[serde(flatten)]
+    pub series: Series,
+}
+
+#[instrument(skip(state, user))]
+pub async fn erase_data(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(erasure): Query<Erasure>,
+) -> Response {
+    if let Err(msg) = erasure.validate(state.auth_configured()) {
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+    let actor = user.map_or_else(|| "anonymous".to_string(), |Extension(u)| u.name);
+    match erasure::erase(&state, &erasure, &actor).await {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => {
+            error!(error = ?err, "erasure failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response()
+        }
+    }
+}
+
+#[instrument(skip(state, user))]
+pub async fn export_zip(
+    State(state): State<AppState>,
+    user: Option<Extension<AuthUser>>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return (StatusCode::BAD_REQUEST, "from must not be after to").into_response();
+    }
+    let (user, pinned) = match user {
+        Some(Extension(user)) => (Some(user.name), user.devices),
+        None => (None, None),
+    };
+    if let (Some(pinned), Some(device)) = (&pinned, &query.device)
+        && !pinned.contains(device)
+    {
+        return (StatusCode::FORBIDDEN, "device not allowed").into_response();
+    }
+    let allowed = |stored: &str| {
+        pinned
+            .as_ref()
+            .is_none_or(|p| p.iter().any(|d| s3::sanitize_path_segment(d) == stored))
+    };
+    let files = match export::select_files(&state, user.as_deref(), &query, allowed).await {
+        Ok(files) => files,
+        Err(err) => {
+            error!(error = ?err, "failed to list day files for export");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "storage error").into_response();
+        }
+    };
+
+    let filename = format!("ahe-export-{}.zip", Utc::now().format("%Y%m%dT%H%M%SZ"));
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        Body::from_stream(export::stream_zip(state, user, query, files)),
+    )
+        .into_response()
 }
@@ -0,0 +1,358 @@
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+
+use crate::aggregate::{DOWNSAMPLED_FIELD, Stats};
+use crate::apple_export::APPLE_DATE_FORMAT;
+use crate::normalize;
+use crate::omh;
+
+// Fields probed, in order, when reading flat items of unknown origin.
+const METRIC_FIELDS: &[&str] = &["type", "name", "metric"];
+const VALUE_FIELDS: &[&str] = &["value", "qty", "Avg", "avg", "bpm", "count"];
+const UNIT_FIELDS: &[&str] = &["unit", "units"];
+const START_FIELDS: &[&str] = &["startDate", "start", "date", "ts", "timestamp", "time"];
+const END_FIELDS: &[&str] = &["endDate", "end"];
+const SOURCE_FIELDS: &[&str] = &["sourceName", "source"];
+
+// Metric names used by Health Auto Export that differ from HealthKit's.
+const ALIASES: &[(&str, &str)] = &[
+    ("blood_oxygen_saturation", "oxygen_saturation"),
+    ("weight_body_mass", "body_mass"),
+    ("walking_running_distance", "distance_walking_running"),
+    ("steps", "step_count"),
+];
+
+pub const BLOOD_PRESSURE_SYSTOLIC: &str = "blood_pressure_systolic";
+pub const BLOOD_PRESSURE_DIASTOLIC: &str = "blood_pressure_diastolic";
+
+/// A single numeric measurement extracted from a stored item.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub metric: String,
+    pub value: f64,
+    pub unit: Option<String>,
+    pub start: DateTime<FixedOffset>,
+    pub end: Option<DateTime<FixedOffset>>,
+    pub source: Option<String>,
+    /// Statistics of the raw samples a downsampled item stands in for; `value` is
+    /// their average.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+}
+
+/// A sample, or a systolic/diastolic pair taken at the same time.
+#[derive(Debug, Clone)]
+pub enum Reading {
+    Single(Sample),
+    BloodPressure { systolic: Sample, diastolic: Sample },
+}
+
+/// Extract the samples carried by one stored item.
+///
+/// Understands Health Auto Export metric objects (`name`, `units`, `data: [...]`),
+/// items imported from Apple's export.xml (including correlations with nested
+/// `Record`s) and flat items such as `{"type": "HeartRate", "bpm": 72, "ts": ...}`.
+/// Items without a numeric value or a parseable timestamp yield nothing.
+pub fn samples(item: &JsonValue) -> Vec<Sample> {
+    let Some(obj) = item.as_object() else {
+        return Vec::new();
+    };
+
+    if let (Some(name), Some(data)) = (
+        obj.get("name").and_then(JsonValue::as_str),
+        obj.get("data").and_then(JsonValue::as_array),
+    ) {
+        let metric = canonical_metric(name);
+        let unit = obj.get("units").and_then(JsonValue::as_str);
+        return data
+            .iter()
+            .filter_map(JsonValue::as_object)
+            .flat_map(|entry| metric_entry_samples(&metric, unit, entry))
+            .collect();
+    }
+
+    if let Some(samples) = omh::samples(obj) {
+        return samples;
+    }
+
+    if let Some(records) = obj.get("Record").and_then(JsonValue::as_array) {
+        return records.iter().flat_map(samples).collect();
+    }
+
+    flat_sample(obj).into_iter().collect()
+}
+
+/// Like [`samples`], but pairs systolic and diastolic readings sharing a timestamp.
+pub fn readings(item: &JsonValue) -> Vec<Reading> {
+    let (pressure, rest): (Vec<Sample>, Vec<Sample>) = samples(item)
+        .into_iter()
+        .partition(|s| s.metric == BLOOD_PRESSURE_SYSTOLIC || s.metric == BLOOD_PRESSURE_DIASTOLIC);
+    let (systolic, mut diastolic): (Vec<Sample>, Vec<Sample>) = pressure
+        .into_iter()
+        .partition(|s| s.metric == BLOOD_PRESSURE_SYSTOLIC);
+
+    let mut out = Vec::with_capacity(rest.len() + systolic.len() + diastolic.len());
+    for sys in systolic {
+        match diastolic.iter().position(|d| d.start == sys.start) {
+            Some(pos) => out.push(Reading::BloodPressure {
+                systolic: sys,
+                diastolic: diastolic.remove(pos),
+            }),
+            None => out.push(Reading::Single(sys)),
+        }
+    }
+    out.extend(diastolic.into_iter().chain(rest).map(Reading::Single));
+    out
+}
+
+/// Value of a sample in the unit used by interchange formats (FHIR, Open mHealth):
+/// body mass in kg, oxygen saturation in percent, everything else unchanged.
+/// Returns `None` when the source unit cannot be converted.
+pub fn standard_value(sample: &Sample) -> Option<f64> {
+    let unit = sample.unit.as_deref().unwrap_or_default();
+    match sample.metric.as_str() {
+        "body_mass" if unit.is_empty() => Some(sample.value),
+        "body_mass" => {
+            normalize::canonical_value(&sample.metric, sample.value, unit).map(|(v, _)| v)
+        }
+        // HealthKit stores saturation as a fraction, Health Auto Export as a percentage.
+        "oxygen_saturation" if sample.value <= 1.0 => Some(sample.value * 100.0),
+        _ => Some(sample.value),
+    }
+}
+
+/// Map HealthKit identifiers and Health Auto Export names onto one snake_case name.
+pub fn canonical_metric(name: &str) -> String {
+    let name = [
+        "HKQuantityTypeIdentifier",
+        "HKCategoryTypeIdentifier",
+        "HKCorrelationTypeIdentifier",
+    ]
+    .iter()
+    .find_map(|p| name.strip_prefix(p))
+    .unwrap_or(name);
+    let snake = to_snake_case(name);
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == snake)
+        .map(|(_, canonical)| canonical.to_string())
+        .unwrap_or(snake)
+}
+
+/// Parse the timestamp formats found in stored items (RFC 3339 or Apple's
+/// "2024-01-01 08:00:00 +0100"); bare dates are taken as midnight UTC.
+pub fn parse_timestamp(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(s, APPLE_DATE_FORMAT))
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+            Some(date.and_hms_opt(0, 0, 0)?.and_utc().fixed_offset())
+        })
+}
+
+fn metric_entry_samples(
+    metric: &str,
+    unit: Option<&str>,
+    entry: &Map<String, JsonValue>,
+) -> Vec<Sample> {
+    let Some(start) = first_str(entry, START_FIELDS).and_then(parse_timestamp) else {
+        return Vec::new();
+    };
+    let sample = |metric: &str, value: f64| Sample {
+        metric: metric.to_string(),
+        value,
+        unit: unit.map(str::to_string),
+        start,
+        end: first_str(entry, END_FIELDS).and_then(parse_timestamp),
+        source: first_str(entry, SOURCE_FIELDS).map(str::to_string),
+        stats: None,
+    };
+
+    // Health Auto Export reports blood pressure as one entry with both readings.
+    if metric == "blood_pressure" {
+        return [
+            (BLOOD_PRESSURE_SYSTOLIC, "systolic"),
+            (BLOOD_PRESSURE_DIASTOLIC, "diastolic"),
+        ]
+        .iter()
+        .filter_map(|(name, field)| Some(sample(name, json_number(entry.get(*field)?)?)))
+        .collect();
+    }
+
+    first_number(entry, VALUE_FIELDS)
+        .map(|value| sample(metric, value))
+        .into_iter()
+        .collect()
+}
+
+fn flat_sample(obj: &Map<String, JsonValue>) -> Option<Sample> {
+    Some(Sample {
+        metric: canonical_metric(first_str(obj, METRIC_FIELDS)?),
+        value: first_number(obj, VALUE_FIELDS)?,
+        unit: first_str(obj, UNIT_FIELDS).map(str::to_string),
+        start: first_str(obj, START_FIELDS).and_then(parse_timestamp)?,
+        end: first_str(obj, END_FIELDS).and_then(parse_timestamp),
+        source: first_str(obj, SOURCE_FIELDS).map(str::to_string),
+        stats: obj
+            .contains_key(DOWNSAMPLED_FIELD)
+            .then(|| stored_stats(obj))
+            .flatten(),
+    })
+}
+
+// Min/max/sum/count kept by a downsampled item.
+fn stored_stats(obj: &Map<String, JsonValue>) -> Option<Stats> {
+    Some(Stats {
+        min: json_number(obj.get("min")?)?,
+        max: json_number(obj.get("max")?)?,
+        sum: json_number(obj.get("sum")?)?,
+        count: obj.get("count")?.as_u64().filter(|c| *c > 0)?,
+    })
+}
+
+fn first_str<'a>(obj: &'a Map<String, JsonValue>, fields: &[&str]) -> Option<&'a str> {
+    fields
+        .iter()
+        .find_map(|f| obj.get(*f).and_then(JsonValue::as_str))
+}
+
+fn first_number(obj: &Map<String, JsonValue>, fields: &[&str]) -> Option<f64> {
+    fields.iter().find_map(|f| json_number(obj.get(*f)?))
+}
+
+// Numbers may arrive as JSON numbers or numeric strings.
+pub fn json_number(value: &JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|n| n.is_finite())
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    let mut prev_lower = false;
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.push(ch.to_ascii_lowercase());
+            prev_lower = false;
+        } else if ch == '-' || ch == ' ' {
+            out.push('_');
+            prev_lower = false;
+        } else {
+            out.push(ch);
+            prev_lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn canonical_metric_unifies_naming_schemes() {
+        for name in [
+            "HKQuantityTypeIdentifierHeartRate",
+            "HeartRate",
+            "heart_rate",
+        ] {
+            assert_eq!(canonical_metric(name), "heart_rate");
+        }
+        assert_eq!(canonical_metric("steps"), "step_count");
+        assert_eq!(
+            canonical_metric("blood_oxygen_saturation"),
+            "oxygen_saturation"
+        );
+        assert_eq!(
+            canonical_metric("HKCategoryTypeIdentifierSleepAnalysis"),
+            "sleep_analysis"
+        );
+    }
+
+    #[test]
+    fn parses_timestamp_formats() {
+        let rfc = parse_timestamp("2026-09-01T08:00:00+02:00").unwrap();
+        let apple = parse_timestamp("2026-09-01 08:00:00 +0200").unwrap();
+        assert_eq!(rfc, apple);
+        assert_eq!(
+            parse_timestamp("2026-09-01").unwrap().to_rfc3339(),
+            "2026-09-01T00:00:00+00:00"
+        );
+        assert!(parse_timestamp("yesterday").is_none());
+    }
+
+    #[test]
+    fn reads_health_auto_export_metrics() {
+        let item = json!({
+            "name": "heart_rate",
+            "units": "count/min",
+            "data": [
+                { "date": "2026-09-01 08:00:00 +0000", "Avg": 61, "source": "Watch" },
+                { "date": "2026-09-01 08:01:00 +0000", "qty": "62.5" },
+                { "date": "not a date", "qty": 70 },
+                { "date": "2026-09-01 08:02:00 +0000" },
+            ],
+        });
+        let samples = samples(&item);
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].metric, "heart_rate");
+        assert_eq!(samples[0].value, 61.0);
+        assert_eq!(samples[0].unit.as_deref(), Some("count/min"));
+        assert_eq!(samples[0].source.as_deref(), Some("Watch"));
+        assert_eq!(samples[1].value, 62.5);
+    }
+
+    #[test]
+    fn splits_health_auto_export_blood_pressure() {
+        let item = json!({
+            "name": "blood_pressure",
+            "units": "mmHg",
+            "data": [{ "date": "2026-09-01 08:00:00 +0000", "systolic": 120, "diastolic": 80 }],
+        });
+        let samples = samples(&item);
+        let metrics: Vec<(&str, f64)> = samples
+            .iter()
+            .map(|s| (s.metric.as_str(), s.value))
+            .collect();
+        assert_eq!(
+            metrics,
+            [
+                (BLOOD_PRESSURE_SYSTOLIC, 120.0),
+                (BLOOD_PRESSURE_DIASTOLIC, 80.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_imported_records_and_flat_items() {
+        let correlation = json!({
+            "kind": "Correlation",
+            "Record": [{
+                "type": "HKQuantityTypeIdentifierBloodPressureSystolic",
+                "value": 118.0,
+                "unit": "mmHg",
+                "startDate": "2026-09-01 08:00:00 +0000",
+                "endDate": "2026-09-01 08:00:30 +0000",
+            }],
+        });
+        let samples_of = samples(&correlation);
+        assert_eq!(samples_of[0].metric, BLOOD_PRESSURE_SYSTOLIC);
+        assert!(samples_of[0].end.is_some());
+
+        let flat = json!({ "type": "HeartRate", "bpm": 72, "ts": "2026-09-01T08:00:00Z" });
+        assert_eq!(samples(&flat)[0].value, 72.0);
+
+        assert!(samples(&json!({ "type": "HeartRate", "ts": "2026-09-01T08:00:00Z" })).is_empty());
+        assert!(samples(&json!([1, 2])).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::metrics;
+
+// Failed attempts are answered after BASE_DELAY * 2^(failures - 1), capped at MAX_DELAY.
+const BASE_DELAY: Duration = Duration::from_millis(200);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+// Stale entries are dropped once this many keys are tracked.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Failed authentication attempts per source IP (`ip:<addr>`) and claimed username
+/// (`user:<name>`), with progressive delays and temporary lockouts.
+pub struct AuthGuard {
+    max_failures: u32,
+    lockout: Duration,
+    entries: Mutex<HashMap<String, Failures>>,
+}
+
+impl AuthGuard {
+    pub fn new(max_failures: u32, lockout: Duration) -> Self {
+        Self {
+            max_failures,
+            lockout,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Remaining lockout if any of `keys` is locked.
+    pub fn locked(&self, keys: &[String]) -> Option<Duration> {
+        let now = Instant::now();
+        let entries = self.entries.lock().expect("auth guard lock poisoned");
+        keys.iter()
+            .filter_map(|key| entries.get(key)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max()
+    }
+
+    /// Count a failure for every key, locking keys that reach the limit.
+    /// Returns how long to delay the response.
+    pub fn record_failure(&self, keys: &[String]) -> Duration {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("auth guard lock poisoned");
+        if entries.len() >= PRUNE_THRESHOLD {
+            let lockout = self.lockout;
+            entries.retain(|_, f| {
+                now.duration_since(f.last) < lockout || f.locked_until.is_some_and(|u| u > now)
+            });
+        }
+
+        let mut worst = 0;
+        for key in keys {
+            let failures = entries.entry(key.clone()).or_insert(Failures {
+                count: 0,
+                last: now,
+                locked_until: None,
+            });
+            // Failures older than the lockout window, or from before an expired lockout, are forgotten.
+            let expired_lock = failures.locked_until.is_some_and(|u| u <= now);
+            if expired_lock || now.duration_since(failures.last) >= self.lockout {
+                failures.count = 0;
+                failures.locked_until = None;
+            }
+            failures.count += 1;
+            failures.last = now;
+            worst = worst.max(failures.count);
+
+            if failures.count >= self.max_failures && failures.locked_until.is_none() {
+                failures.locked_until = Some(now + self.lockout);
+                let kind = key.split_once(':').map_or("unknown", |(kind, _)| kind);
+                metrics::inc_auth_lockout(kind);
+                warn!(
+                    event = "auth_lockout",
+                    key = %key,
+                    kind,
+                    failures = failures.count,
+                    lockout_secs = self.lockout.as_secs(),
+                    "authentication locked out after repeated failures"
+                );
+            }
+        }
+        BASE_DELAY
+            .saturating_mul(1 << worst.saturating_sub(1).min(16))
+            .min(MAX_DELAY)
+    }
+
+    /// Forget the failures of `key` after a successful login.
+    pub fn record_success(&self, key: &str) {
+        let mut entries = self.entries.lock().expect("auth guard lock poisoned");
+        if entries.get(key).is_some_and(|f| f.locked_until.is_none()) {
+            entries.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(ip: &str, user: &str) -> Vec<String> {
+        vec![format!("ip:{ip}"), format!("user:{user}")]
+    }
+
+    #[test]
+    fn delays_grow_and_lock_out_at_the_limit() {
+        let guard = AuthGuard::new(3, Duration::from_secs(60));
+        let attempt = keys("10.0.0.1", "alice");
+        assert_eq!(guard.record_failure(&attempt), BASE_DELAY);
+        assert_eq!(guard.record_failure(&attempt), BASE_DELAY * 2);
+        assert!(guard.locked(&attempt).is_none());
+        assert_eq!(guard.record_failure(&attempt), BASE_DELAY * 4);
+
+        let remaining = guard.locked(&attempt).unwrap();
+        assert!(remaining <= Duration::from_secs(60));
+        // The username stays locked from another address, the address for another user.
+        assert!(guard.locked(&keys("10.0.0.2", "alice")).is_some());
+        assert!(guard.locked(&keys("10.0.0.1", "bob")).is_some());
+        assert!(guard.locked(&keys("10.0.0.2", "bob")).is_none());
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let guard = AuthGuard::new(100, Duration::from_secs(60));
+        let attempt = keys("10.0.0.1", "alice");
+        let delay = (0..20).map(|_| guard.record_failure(&attempt)).last();
+        assert_eq!(delay, Some(MAX_DELAY));
+    }
+
+    #[test]
+    fn success_forgets_failures_but_not_lockouts() {
+        let guard = AuthGuard::new(2, Duration::from_secs(60));
+        guard.record_failure(&keys("10.0.0.1", "alice"));
+        guard.record_success("user:alice");
+        assert_eq!(
+            guard.record_failure(&["user:alice".to_string()]),
+            BASE_DELAY
+        );
+
+        guard.record_failure(&["user:alice".to_string()]);
+        guard.record_success("user:alice");
+        assert!(guard.locked(&["user:alice".to_string()]).is_some());
+    }
+
+    #[test]
+    fn lockouts_expire() {
+        let guard = AuthGuard::new(1, Duration::from_millis(10));
+        let attempt = keys("10.0.0.1", "alice");
+        guard.record_failure(&attempt);
+        assert!(guard.locked(&attempt).is_some());
+        std::thread::sleep(Duration::from_millis(15));
+        assert!(guard.locked(&attempt).is_none());
+        assert_eq!(guard.record_failure(&attempt), BASE_DELAY);
+    }
+}
@@ -1,26 +1,53 @@
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use aws_config::BehaviorVersion;
 use aws_sdk_s3::Client as S3Client;
 use axum::{
     Router, middleware,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
 use clap::Parser;
 use mimalloc::MiMalloc;
 use tracing::{debug, error, info};
 
+mod aggregate;
+mod api_keys;
+mod apple_export;
 mod auth;
+mod cli;
 mod config;
+mod encryption;
+mod erasure;
 mod error;
+mod export;
+mod fhir;
+mod gpx;
 mod handlers;
+mod items;
+mod jwt;
+mod lockout;
+mod maintenance;
 mod metrics;
+mod migrate;
+mod normalize;
+mod object_options;
+mod omh;
+mod ratelimit;
+mod retention;
+mod rollup;
 mod s3;
+mod scopes;
+mod signature;
 mod state;
+mod summary;
 mod telemetry;
+mod tls;
+mod users;
 
 use crate::config::Config;
 use crate::error::Result;
+use crate::scopes::Scope;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -39,10 +66,34 @@ async fn main() -> Result<()> {
         workers = %cfg.workers,
         queue_cap = %cfg.queue_cap,
         s3_path_style = %cfg.s3_path_style,
+        normalize_units = %cfg.normalize_units,
+        storage_format = ?cfg.storage_format,
         basic_auth_enabled = %cfg.basic_user.is_some() && cfg.basic_pass.is_some(),
+        users_file = ?cfg.users_file,
+        api_keys_file = ?cfg.api_keys_file,
+        jwt_jwks = ?cfg.jwt_jwks,
+        signing_clients_file = ?cfg.signing_clients_file,
         "Parsed configuration"
     );
 
+    // Commands that do not touch the bucket run before any S3 setup
+    match &cfg.command {
+        Some(config::Command::HashPassword { algorithm }) => {
+            return cli::hash_password(*algorithm);
+        }
+        Some(config::Command::GenerateApiKey {
+            user,
+            devices,
+            scopes,
+        }) => {
+            return cli::generate_api_key(user, devices, scopes);
+        }
+        Some(config::Command::GenerateEncryptionKey { id }) => {
+            return cli::generate_encryption_key(id);
+        }
+        _ => {}
+    }
+
     // AWS config via default chain (env, profile, etc.)
     let aws_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
     let s3_conf = aws_sdk_s3::config::Builder::from(&aws_config)
@@ -58,21 +109,83 @@ async fn main() -> Result<()> {
         debug!(bucket = %cfg.bucket, "S3 bucket reachable");
     }
 
-    let (app_state, rx) = state::build_state(&cfg, s3);
+    let (app_state, rx) = state::build_state(&cfg, s3)?;
+    if let Some(command) = cfg.command.clone() {
+        debug!(?command, "Running command instead of server");
+        return cli::run(command, &app_state).await;
+    }
+    let tls = cfg.tls().map_err(error::Error::Config)?;
+
+    // Fail fast on an unreadable JWKS instead of rejecting every token later
+    if let Some(jwt) = &app_state.jwt {
+        jwt.refresh().await?;
+    }
+
     let _workers = state::spawn_workers(app_state.clone(), rx, cfg.workers);
     debug!(workers = %cfg.workers, "Spawned worker tasks");
+    if app_state.retention.is_some() {
+        let interval = Duration::from_secs(cfg.retention_sweep_interval_secs.max(1));
+        retention::spawn_sweeper(app_state.clone(), interval);
+        debug!(?interval, "Spawned retention sweeper");
+    }
+    if app_state.rollup.is_some() {
+        let interval = Duration::from_secs(cfg.rollup_interval_secs.max(1));
+        rollup::spawn_rollup(app_state.clone(), interval);
+        debug!(?interval, "Spawned rollup job");
+    }
 
     // Build routers
     let ingest_router = Router::new()
         .route("/ingest", post(handlers::ingest))
+        .route("/import/apple", post(handlers::import_apple))
+        .route("/import/apple/{job}", get(handlers::import_status))
+        .route_layer(middleware::from_fn_with_state(
+            Scope::Ingest,
+            auth::require_scope,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::authenticate,
+        ));
+
+    let read_router = Router::new()
+        .route("/fhir/Observation", get(handlers::fhir_observations))
+        .route("/omh/data-points", get(handlers::omh_data_points))
+        .route("/query", get(handlers::query_samples))
+        .route("/aggregate", get(handlers::aggregate_samples))
+        .route("/export", get(handlers::export_zip))
+        .route("/devices", get(handlers::list_devices))
+        .route("/devices/{device}/days", get(handlers::list_device_days))
+        .route("/devices/{device}/days/{date}", get(handlers::device_day))
+        .route(
+            "/devices/{device}/days/{date}/summary",
+            get(handlers::device_day_summary),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            Scope::Read,
+            auth::require_scope,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::authenticate,
+        ));
+
+    let admin_router = Router::new()
+        .route("/admin/data", delete(handlers::erase_data))
+        .route_layer(middleware::from_fn_with_state(
+            Scope::Admin,
+            auth::require_scope,
+        ))
         .route_layer(middleware::from_fn_with_state(
             app_state.clone(),
-            auth::basic_auth,
+            auth::authenticate,
         ));
 
     let app = Router::new()
         .route("/health", get(handlers::health))
         .merge(ingest_router)
+        .merge(read_router)
+        .merge(admin_router)
         .layer(tower_http::trace::TraceLayer::new_for_http())
         .with_state(app_state);
 
@@ -81,8 +194,20 @@ async fn main() -> Result<()> {
         None => ([0, 0, 0, 0], cfg.port).into(),
     };
 
-    info!(%addr, "Starting server");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
-    Ok(())
+    match tls {
+        Some(tls) => {
+            info!(%addr, client_ca = ?tls.client_ca, "Starting server with TLS");
+            tls::serve(listener, app, tls).await
+        }
+        None => {
+            info!(%addr, "Starting server");
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?;
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use tracing::{instrument, warn};
+
+use crate::error::{Error, Result};
+use crate::s3::{self, StoredObject};
+use crate::state::AppState;
+
+// Held by jobs that rewrite or delete stored objects (retention, rollups, erasure, key
+// rotation) so the jobs of one process never touch the same object at the same time.
+static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+pub type MaintenanceGuard = tokio::sync::MutexGuard<'static, ()>;
+
+/// Tier a stored day file belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    /// `<user>/<device>/<date>.json`, as written by ingest and imports.
+    Raw,
+    /// `<user>/_rollup/<device>/<date>.json`, aggregates moved out by rollups.
+    Rollup,
+}
+
+/// A day file found by [`day_files`].
+#[derive(Debug, Clone)]
+pub struct DayFile {
+    pub key: String,
+    pub user: Option<String>,
+    /// Stored (sanitized) device name.
+    pub device: String,
+    pub date: NaiveDate,
+    pub tier: Tier,
+}
+
+impl DayFile {
+    fn parse(root: &str, key: &str) -> Option<Self> {
+        let relative = key.strip_prefix(root)?;
+        let ((user, device, date), tier) = match s3::parse_stored_day(relative) {
+            Some(found) => (found, Tier::Raw),
+            None => (s3::parse_stored_rollup(relative)?, Tier::Rollup),
+        };
+        Some(Self {
+            key: key.to_string(),
+            user: user.map(str::to_string),
+            device: device.to_string(),
+            date,
+            tier,
+        })
+    }
+}
+
+/// Run `job` every `interval` until the process exits.
+pub fn spawn_periodic<F, Fut>(
+    state: AppState,
+    interval: Duration,
+    job: F,
+) -> tokio::task::JoinHandle<()>
+where
+    F: Fn(AppState) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            job(state.clone()).await;
+        }
+    })
+}
+
+/// Take the maintenance lock, e.g. to delete objects a running job might rewrite.
+pub async fn lock() -> MaintenanceGuard {
+    LOCK.lock().await
+}
+
+/// Every object under the prefix, for all users. The returned guard holds the
+/// maintenance lock; keep it for as long as the objects are being rewritten.
+#[instrument(skip(state))]
+pub async fn objects(state: &AppState) -> Result<(MaintenanceGuard, Vec<StoredObject>)> {
+    let guard = lock().await;
+    let root = state.prefix.clone().unwrap_or_default();
+    Ok((guard, s3::list_objects(state, &root, None).await?))
+}
+
+/// Like [`objects`], restricted to day files of both tiers.
+pub async fn day_files(state: &AppState) -> Result<(MaintenanceGuard, Vec<DayFile>)> {
+    let (guard, objects) = objects(state).await?;
+    let root = state.prefix.clone().unwrap_or_default();
+    let days = objects
+        .iter()
+        .filter_map(|obj| DayFile::parse(&root, &obj.key))
+        .collect();
+    Ok((guard, days))
+}
+
+/// Log that `job` failed on one object. Jobs keep going after a failure; the object is
+/// retried on the next run.
+pub fn log_failure(job: &str, key: &str, err: &Error) {
+    warn!(error = ?err, %key, job, "maintenance job failed for object");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_day_files_of_both_tiers() {
+        let date = NaiveDate::from_ymd_opt(2026, 9, 1).unwrap();
+        let raw = DayFile::parse("health/", "health/alice/watch/2026-09-01.json").unwrap();
+        assert_eq!(
+            (raw.user.as_deref(), raw.device.as_str(), raw.date, raw.tier),
+            (Some("alice"), "watch", date, Tier::Raw)
+        );
+        let legacy = DayFile::parse("health/", "health/watch/2026-09-01.json").unwrap();
+        assert_eq!((legacy.user, legacy.tier), (None, Tier::Raw));
+
+        let rollup =
+            DayFile::parse("health/", "health/alice/_rollup/watch/2026-09-01.json").unwrap();
+        assert_eq!(
+            (rollup.user.as_deref(), rollup.device.as_str(), rollup.tier),
+            (Some("alice"), "watch", Tier::Rollup)
+        );
+        let rollup = DayFile::parse("", "_rollup/watch/2026-09-01.json").unwrap();
+        assert_eq!((rollup.user, rollup.tier), (None, Tier::Rollup));
+    }
+
+    #[test]
+    fn skips_other_objects() {
+        for key in [
+            "health/_audit/2026-09-01.json",
+            "health/alice/watch/2026-09-01.summary.json",
+            "health/alice/_x/2026-09-01.json",
+            "health/_rollup/_rollup/watch/2026-09-01.json",
+            "health/a/b/c/2026-09-01.json",
+            "other/alice/watch/2026-09-01.json",
+        ] {
+            assert!(DayFile::parse("health/", key).is_none(), "{key}");
+        }
+    }
+}
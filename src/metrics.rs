@@ -6,6 +6,12 @@ use opentelemetry::{KeyValue, global};
 pub struct Metrics {
     ingest_requests_total: Counter<u64>,
     jobs_inflight: UpDownCounter<i64>,
+    imported_items_total: Counter<u64>,
+    ingest_rejected_total: Counter<u64>,
+    auth_lockouts_total: Counter<u64>,
+    retention_deleted_files_total: Counter<u64>,
+    retention_items_total: Counter<u64>,
+    rollup_items_total: Counter<u64>,
 }
 
 static METRICS: Lazy<Metrics> = Lazy::new(|| {
@@ -21,9 +27,45 @@ static METRICS: Lazy<Metrics> = Lazy::new(|| {
         .with_description("Number of background jobs currently in-flight")
         .build();
 
+    let imported_items_total = meter
+        .u64_counter("ahe_imported_items_total")
+        .with_description("Total number of items imported from Apple Health exports")
+        .build();
+
+    let ingest_rejected_total = meter
+        .u64_counter("ahe_ingest_rejected_total")
+        .with_description("Ingest requests rejected by rate limits or daily quotas")
+        .build();
+
+    let auth_lockouts_total = meter
+        .u64_counter("ahe_auth_lockouts_total")
+        .with_description("Authentication lockouts after repeated failed attempts")
+        .build();
+
+    let retention_deleted_files_total = meter
+        .u64_counter("ahe_retention_deleted_files_total")
+        .with_description("Day files deleted by the retention sweeper")
+        .build();
+
+    let retention_items_total = meter
+        .u64_counter("ahe_retention_items_total")
+        .with_description("Stored items deleted or downsampled by the retention sweeper")
+        .build();
+
+    let rollup_items_total = meter
+        .u64_counter("ahe_rollup_items_total")
+        .with_description("Stored items moved to the rollup tier as aggregates")
+        .build();
+
     Metrics {
         ingest_requests_total,
         jobs_inflight,
+        imported_items_total,
+        ingest_rejected_total,
+        auth_lockouts_total,
+        retention_deleted_files_total,
+        retention_items_total,
+        rollup_items_total,
     }
 });
 
@@ -42,3 +84,35 @@ pub fn inc_jobs_inflight() {
 pub fn dec_jobs_inflight() {
     METRICS.jobs_inflight.add(-1, &[]);
 }
+
+pub fn add_imported_items(count: u64) {
+    METRICS.imported_items_total.add(count, &[]);
+}
+
+pub fn inc_ingest_rejected(reason: &'static str) {
+    METRICS
+        .ingest_rejected_total
+        .add(1, &[KeyValue::new("reason", reason)]);
+}
+
+pub fn inc_auth_lockout(kind: &str) {
+    METRICS
+        .auth_lockouts_total
+        .add(1, &[KeyValue::new("kind", kind.to_string())]);
+}
+
+pub fn inc_retention_deleted_files() {
+    METRICS.retention_deleted_files_total.add(1, &[]);
+}
+
+pub fn add_retention_items(action: &'static str, count: u64) {
+    if count > 0 {
+        METRICS
+            .retention_items_total
+            .add(count, &[KeyValue::new("action", action)]);
+    }
+}
+
+pub fn add_rollup_items(count: u64) {
+    METRICS.rollup_items_total.add(count, &[]);
+}
@@ -0,0 +1,192 @@
+use tracing::{debug, error, info, instrument};
+
+use crate::error::{Error, Result};
+use crate::maintenance::Tier;
+use crate::rollup;
+use crate::s3;
+use crate::state::AppState;
+
+// Attempts at merging a moved day file into a user's day file that keeps changing.
+const MERGE_ATTEMPTS: usize = 5;
+
+/// Outcome of moving the unauthenticated layout under a user prefix.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// Day and rollup tier files moved (or, in a dry run, to be moved), old key and new key.
+    pub moved: Vec<(String, String)>,
+    /// Files merged into a day file already present under the user prefix.
+    pub merged: u64,
+    pub failed: Vec<String>,
+    /// Files written under the user prefix whose old object could not be deleted; they
+    /// must be deleted before a rerun, which would merge them again.
+    pub undeleted: Vec<String>,
+}
+
+/// Move the day files stored without authentication (`prefix/<device>/<date>.json`),
+/// and their rollup tier files, under `user`'s prefix, so they stay readable once that
+/// user authenticates. A file already present under the user prefix is merged with the
+/// moved one, the day summary is rebuilt, and the old objects are deleted.
+#[instrument(skip(state))]
+pub async fn migrate_legacy_layout(
+    state: &AppState,
+    user: &str,
+    dry_run: bool,
+) -> Result<MigrationReport> {
+    if user.is_empty() {
+        return Err(Error::Config("user must not be empty".to_string()));
+    }
+    let root = state.prefix.clone().unwrap_or_default();
+    let mut report = MigrationReport::default();
+    for obj in s3::list_objects(state, &root, None).await? {
+        let key = obj.key;
+        let Some((target, tier)) = legacy_target(&state.prefix, &key, user) else {
+            continue;
+        };
+        if !dry_run {
+            match move_day_file(state, &key, &target, tier).await {
+                Ok(Moved { merged, deleted }) => {
+                    report.merged += u64::from(merged);
+                    if !deleted {
+                        report.undeleted.push(key.clone());
+                    }
+                }
+                Err(err) => {
+                    error!(%key, error = ?err, "failed to migrate day file");
+                    report.failed.push(key);
+                    continue;
+                }
+            }
+        }
+        debug!(from = %key, to = %target, dry_run, "migrated day file");
+        report.moved.push((key, target));
+    }
+    info!(
+        event = "legacy_layout_migrated",
+        %user,
+        dry_run,
+        moved = report.moved.len(),
+        merged = report.merged,
+        failed = report.failed.len(),
+        undeleted = report.undeleted.len(),
+        "legacy layout migration finished"
+    );
+    Ok(report)
+}
+
+// Key under `user`'s prefix for a day or rollup tier file stored without
+// authentication, or `None` if `key` is not one (for example because it already sits
+// under a user prefix).
+fn legacy_target(prefix: &Option<String>, key: &str, user: &str) -> Option<(String, Tier)> {
+    let relative = key.strip_prefix(prefix.as_deref().unwrap_or_default())?;
+    if let Some((device, date)) = s3::parse_rollup_key(relative) {
+        return Some((
+            s3::rollup_key(prefix, Some(user), device, date),
+            Tier::Rollup,
+        ));
+    }
+    let (device, date) = s3::parse_day_key(relative)?;
+    Some((
+        s3::s3_key_for_device_date(prefix, Some(user), device, date),
+        Tier::Raw,
+    ))
+}
+
+struct Moved {
+    merged: bool,
+    deleted: bool,
+}
+
+// Merge one day or rollup tier file into its new key, rewrite the day summary, then
+// delete the old file and its summary. The new file is written first, and only if it
+// did not change since it was read, so neither an interrupted run nor a concurrent
+// ingest loses data.
+async fn move_day_file(state: &AppState, from: &str, to: &str, tier: Tier) -> Result<Moved> {
+    let Some(incoming) = s3::load_json(state, from).await? else {
+        return Ok(Moved {
+            merged: false,
+            deleted: true,
+        });
+    };
+    for attempt in 1..=MERGE_ATTEMPTS {
+        let (existing, etag) = s3::load_json_versioned(state, to).await?.unzip();
+        let merged = existing.is_some();
+        let value = match (existing, tier) {
+            (Some(existing), Tier::Raw) => s3::merge_json(existing, incoming.clone()),
+            (Some(existing), Tier::Rollup) => rollup::merge_files(existing, incoming.clone()),
+            (None, _) => incoming.clone(),
+        };
+        if s3::put_json_if_unchanged(state, to, &value, etag.as_deref()).await? {
+            let old = match tier {
+                Tier::Raw => {
+                    s3::save_summary(state, to, &value).await?;
+                    vec![from.to_string(), s3::summary_key(from)]
+                }
+                Tier::Rollup => {
+                    if let Some((Some(user), device, date)) = to
+                        .strip_prefix(state.prefix.as_deref().unwrap_or_default())
+                        .and_then(s3::parse_stored_rollup)
+                    {
+                        let day_key =
+                            s3::s3_key_for_device_date(&state.prefix, Some(user), device, date);
+                        s3::refresh_summary(state, &day_key).await;
+                    }
+                    vec![from.to_string()]
+                }
+            };
+            let failed = s3::delete_objects(state, &old).await?;
+            return Ok(Moved {
+                merged,
+                deleted: failed.is_empty(),
+            });
+        }
+        debug!(%to, attempt, "day file changed during the migration, retrying");
+    }
+    Err(Error::Migration(format!(
+        "{to} kept changing during the migration"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_legacy_day_files_under_the_user() {
+        let prefix = Some("health/".to_string());
+        assert_eq!(
+            legacy_target(&prefix, "health/watch/2026-09-01.json", "alice"),
+            Some(("health/alice/watch/2026-09-01.json".to_string(), Tier::Raw))
+        );
+        assert_eq!(
+            legacy_target(&None, "watch/2026-09-01.json", "bob:smith"),
+            Some(("bob_smith/watch/2026-09-01.json".to_string(), Tier::Raw))
+        );
+    }
+
+    #[test]
+    fn moves_legacy_rollup_files_under_the_user() {
+        let prefix = Some("health/".to_string());
+        assert_eq!(
+            legacy_target(&prefix, "health/_rollup/watch/2026-09-01.json", "alice"),
+            Some((
+                "health/alice/_rollup/watch/2026-09-01.json".to_string(),
+                Tier::Rollup
+            ))
+        );
+    }
+
+    #[test]
+    fn leaves_other_keys_alone() {
+        let prefix = Some("health/".to_string());
+        for key in [
+            "health/alice/watch/2026-09-01.json",
+            "other/watch/2026-09-01.json",
+            "health/watch/notes.json",
+            "health/watch/2026-9-1.json",
+            "health/2026-09-01.json",
+            "health//2026-09-01.json",
+        ] {
+            assert_eq!(legacy_target(&prefix, key, "alice"), None, "{key}");
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use serde_json::{Map, Value as JsonValue};
+
+use crate::items;
+
+// Numeric fields converted on Health Auto Export metric entries.
+const ENTRY_VALUE_FIELDS: &[&str] = &["qty", "Min", "Avg", "Max"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Energy,
+    Temperature,
+    Speed,
+}
+
+impl Dimension {
+    fn canonical_unit(self) -> &'static str {
+        match self {
+            Dimension::Length => "m",
+            Dimension::Mass => "kg",
+            Dimension::Energy => "kJ",
+            Dimension::Temperature => "degC",
+            Dimension::Speed => "m/s",
+        }
+    }
+
+    // (factor, offset) such that canonical = value * factor + offset.
+    fn conversion(self, unit: &str) -> Option<(f64, f64)> {
+        let conversion = match (self, unit) {
+            (Dimension::Length, "m") => (1.0, 0.0),
+            (Dimension::Length, "km") => (1000.0, 0.0),
+            (Dimension::Length, "cm") => (0.01, 0.0),
+            (Dimension::Length, "mm") => (0.001, 0.0),
+            (Dimension::Length, "mi") => (1609.344, 0.0),
+            (Dimension::Length, "yd") => (0.9144, 0.0),
+            (Dimension::Length, "ft") => (0.3048, 0.0),
+            (Dimension::Length, "in") => (0.0254, 0.0),
+            (Dimension::Mass, "kg") => (1.0, 0.0),
+            (Dimension::Mass, "g") => (0.001, 0.0),
+            (Dimension::Mass, "lb" | "lbs") => (0.453_592_37, 0.0),
+            (Dimension::Mass, "oz") => (0.028_349_523_125, 0.0),
+            (Dimension::Mass, "st") => (6.350_293_18, 0.0),
+            (Dimension::Energy, "kJ") => (1.0, 0.0),
+            (Dimension::Energy, "J") => (0.001, 0.0),
+            // HealthKit's "Cal" is the dietary (kilo)calorie.
+            (Dimension::Energy, "kcal" | "Cal") => (4.184, 0.0),
+            (Dimension::Energy, "cal") => (0.004_184, 0.0),
+            (Dimension::Temperature, "degC" | "°C" | "C") => (1.0, 0.0),
+            (Dimension::Temperature, "degF" | "°F" | "F") => (5.0 / 9.0, -32.0 * 5.0 / 9.0),
+            (Dimension::Temperature, "K") => (1.0, -273.15),
+            (Dimension::Speed, "m/s") => (1.0, 0.0),
+            (Dimension::Speed, "km/hr" | "km/h") => (1.0 / 3.6, 0.0),
+            (Dimension::Speed, "mi/hr" | "mph") => (0.447_04, 0.0),
+            (Dimension::Speed, "ft/s") => (0.3048, 0.0),
+            _ => return None,
+        };
+        Some(conversion)
+    }
+}
+
+// Metrics with a known physical dimension; anything else is left untouched.
+fn dimension(metric: &str) -> Option<Dimension> {
+    match metric {
+        "body_mass" | "lean_body_mass" => Some(Dimension::Mass),
+        "height" | "walking_step_length" | "waist_circumference" => Some(Dimension::Length),
+        m if m.contains("distance") => Some(Dimension::Length),
+        m if m.contains("energy") => Some(Dimension::Energy),
+        m if m.contains("temperature") => Some(Dimension::Temperature),
+        m if m.contains("speed") => Some(Dimension::Speed),
+        _ => None,
+    }
+}
+
+/// Convert `value` of a known metric from `unit` into the canonical SI unit set
+/// (m, kg, kJ, degC, m/s). Returns the converted value and the canonical unit.
+pub fn canonical_value(metric: &str, value: f64, unit: &str) -> Option<(f64, &'static str)> {
+    let dimension = dimension(metric)?;
+    let (factor, offset) = dimension.conversion(unit)?;
+    Some((value * factor + offset, dimension.canonical_unit()))
+}
+
+/// Normalize every item of an ingest payload in place.
+pub fn normalize_payload(payload: &mut JsonValue) {
+    match payload {
+        JsonValue::Array(items) => items.iter_mut().for_each(normalize_item),
+        item => normalize_item(item),
+    }
+}
+
+/// Convert known metrics of one item to canonical units, keeping the original value
+/// and unit in `original_*` fields. Unknown metrics and units are left as received.
+pub fn normalize_item(item: &mut JsonValue) {
+    let Some(obj) = item.as_object_mut() else {
+        return;
+    };
+
+    // Health Auto Export metric object: one unit for all entries.
+    if let (Some(name), Some(JsonValue::String(units))) = (
+        obj.get("name").and_then(JsonValue::as_str),
+        obj.get("units"),
+    ) && obj.get("data").is_some_and(JsonValue::is_array)
+    {
+        let metric = items::canonical_metric(name);
+        let Some(dimension) = dimension(&metric) else {
+            return;
+        };
+        let original = units.clone();
+        let Some(conversion) = dimension.conversion(&original) else {
+            return;
+        };
+        if original == dimension.canonical_unit() {
+            return;
+        }
+        if let Some(JsonValue::Array(entries)) = obj.get_mut("data") {
+            for entry in entries.iter_mut().filter_map(JsonValue::as_object_mut) {
+                for field in ENTRY_VALUE_FIELDS {
+                    convert_field(entry, field, conversion);
+                }
+            }
+        }
+        obj.insert("units".to_string(), dimension.canonical_unit().into());
+        obj.insert("original_units".to_string(), original.into());
+        return;
+    }
+
+    // Correlations from an Apple Health import carry their samples as nested records.
+    if let Some(JsonValue::Array(records)) = obj.get_mut("Record") {
+        records.iter_mut().for_each(normalize_item);
+    }
+
+    normalize_flat(obj);
+}
+
+fn normalize_flat(obj: &mut Map<String, JsonValue>) {
+    let Some(metric) = ["type", "name", "metric"]
+        .iter()
+        .find_map(|f| obj.get(*f).and_then(JsonValue::as_str))
+        .map(items::canonical_metric)
+    else {
+        return;
+    };
+    let Some(unit_field) = ["unit", "units"]
+        .into_iter()
+        .find(|f| obj.get(*f).is_some_and(JsonValue::is_string))
+    else {
+        return;
+    };
+    let Some(value_field) = ["value", "qty"]
+        .into_iter()
+        .find(|f| obj.get(*f).and_then(items::json_number).is_some())
+    else {
+        return;
+    };
+
+    let unit = obj[unit_field].as_str().unwrap_or_default().to_string();
+    let value = obj.get(value_field).and_then(items::json_number);
+    let Some((converted, canonical)) = value.and_then(|v| canonical_value(&metric, v, &unit))
+    else {
+        return;
+    };
+    if unit == canonical {
+        return;
+    }
+    let original_value = obj.insert(value_field.to_string(), converted.into());
+    obj.insert(unit_field.to_string(), canonical.into());
+    if let Some(original_value) = original_value {
+        obj.insert("original_value".to_string(), original_value);
+    }
+    obj.insert("original_unit".to_string(), unit.into());
+}
+
+fn convert_field(entry: &mut Map<String, JsonValue>, field: &str, (factor, offset): (f64, f64)) {
+    let Some(value) = entry.get(field).and_then(items::json_number) else {
+        return;
+    };
+    if let Some(original) = entry.insert(field.to_string(), (value * factor + offset).into()) {
+        entry.insert(format!("original_{field}"), original);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_converts(metric: &str, value: f64, unit: &str, expected: f64, canonical: &str) {
+        let (converted, unit) = canonical_value(metric, value, unit).unwrap();
+        assert!(
+            (converted - expected).abs() < 1e-9,
+            "{metric}: {converted} != {expected}"
+        );
+        assert_eq!(unit, canonical);
+    }
+
+    #[test]
+    fn converts_known_metrics_to_si() {
+        assert_converts("body_mass", 150.0, "lb", 68.038_855_5, "kg");
+        assert_converts("body_mass", 70_000.0, "g", 70.0, "kg");
+        assert_converts("walking_running_distance", 1.0, "mi", 1609.344, "m");
+        assert_converts("active_energy", 100.0, "kcal", 418.4, "kJ");
+        assert_converts("body_temperature", 98.6, "degF", 37.0, "degC");
+        assert_converts("body_temperature", 310.15, "K", 37.0, "degC");
+        assert_converts("walking_speed", 3.6, "km/hr", 1.0, "m/s");
+    }
+
+    #[test]
+    fn leaves_unknown_metrics_and_units_alone() {
+        assert_eq!(canonical_value("heart_rate", 60.0, "count/min"), None);
+        assert_eq!(canonical_value("body_mass", 1.0, "furlong"), None);
+        assert_eq!(canonical_value("height", 1.0, "kg"), None);
+    }
+}
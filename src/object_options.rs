@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use aws_sdk_s3::operation::get_object::builders::GetObjectFluentBuilder;
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use aws_sdk_s3::types::{ServerSideEncryption, StorageClass};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as B64;
+use md5::{Digest, Md5};
+
+use crate::config::{Config, SseMode};
+use crate::error::{Error, Result};
+
+/// Version of the stored object layout, recorded with `AHE_OBJECT_METADATA`.
+pub const SCHEMA_VERSION: &str = "1";
+
+const META_USER: &str = "ahe-user";
+const META_DEVICE: &str = "ahe-device";
+const META_ITEMS: &str = "ahe-items";
+const META_SCHEMA_VERSION: &str = "ahe-schema-version";
+
+// S3 limits per object.
+const MAX_TAGS: usize = 10;
+const MAX_TAG_KEY_LEN: usize = 128;
+const MAX_TAG_VALUE_LEN: usize = 256;
+
+/// Server-side encryption, storage class, tags and metadata applied to every object
+/// the server writes.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectOptions {
+    sse: Option<ServerSideEncryption>,
+    sse_kms_key_id: Option<String>,
+    sse_customer_key: Option<CustomerKey>,
+    storage_class: Option<StorageClass>,
+    /// URL-encoded `key=value&...` as sent in `x-amz-tagging`.
+    tagging: Option<String>,
+    metadata: bool,
+}
+
+// An SSE-C key in the form S3 expects it in request headers.
+#[derive(Clone)]
+struct CustomerKey {
+    key: String,
+    key_md5: String,
+}
+
+impl std::fmt::Debug for CustomerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomerKey(..)")
+    }
+}
+
+/// What an object holds, for its metadata.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ObjectInfo<'a> {
+    pub user: Option<&'a str>,
+    pub device: Option<&'a str>,
+    pub items: Option<usize>,
+}
+
+impl ObjectOptions {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let invalid = |msg: String| Err(Error::Config(msg));
+        let mut options = Self {
+            metadata: config.object_metadata,
+            ..Self::default()
+        };
+        match config.sse {
+            Some(SseMode::SseS3) => options.sse = Some(ServerSideEncryption::Aes256),
+            Some(SseMode::SseKms) => {
+                options.sse = Some(ServerSideEncryption::AwsKms);
+                options.sse_kms_key_id = config.sse_kms_key_id.clone();
+            }
+            Some(SseMode::SseC) => {
+                let Some(path) = &config.sse_customer_key_file else {
+                    return invalid("--sse sse-c requires --sse-customer-key-file".to_string());
+                };
+                let key = B64
+                    .decode(std::fs::read_to_string(path)?.trim())
+                    .ok()
+                    .filter(|k| k.len() == 32)
+                    .ok_or_else(|| {
+                        Error::Config(format!(
+                            "{}: expected a base64 encoded 32-byte key",
+                            path.display()
+                        ))
+                    })?;
+                options.sse_customer_key = Some(CustomerKey {
+                    key: B64.encode(&key),
+                    key_md5: B64.encode(Md5::digest(&key)),
+                });
+            }
+            None => {}
+        }
+        if config.sse_kms_key_id.is_some() && config.sse != Some(SseMode::SseKms) {
+            return invalid("--sse-kms-key-id requires --sse sse-kms".to_string());
+        }
+        if config.sse_customer_key_file.is_some() && config.sse != Some(SseMode::SseC) {
+            return invalid("--sse-customer-key-file requires --sse sse-c".to_string());
+        }
+        if let Some(name) = &config.storage_class {
+            options.storage_class = Some(parse_storage_class(name)?);
+        }
+        if let Some(spec) = &config.object_tags {
+            options.tagging = Some(parse_tags(spec)?);
+        }
+        Ok(options)
+    }
+
+    /// Set encryption, storage class, tags and (with `AHE_OBJECT_METADATA`) descriptive
+    /// metadata on a write; `metadata` carries entries the caller needs stored anyway.
+    pub fn apply_put(
+        &self,
+        request: PutObjectFluentBuilder,
+        info: ObjectInfo<'_>,
+        mut metadata: HashMap<String, String>,
+    ) -> PutObjectFluentBuilder {
+        if self.metadata {
+            metadata.extend(describe(info));
+        }
+        let request = request
+            .set_server_side_encryption(self.sse.clone())
+            .set_ssekms_key_id(self.sse_kms_key_id.clone())
+            .set_storage_class(self.storage_class.clone())
+            .set_tagging(self.tagging.clone())
+            .set_metadata((!metadata.is_empty()).then_some(metadata));
+        match &self.sse_customer_key {
+            Some(key) => request
+                .sse_customer_algorithm("AES256")
+                .sse_customer_key(&key.key)
+                .sse_customer_key_md5(&key.key_md5),
+            None => request,
+        }
+    }
+
+    /// Add the SSE-C key to a read; objects written with one cannot be read without it.
+    pub fn apply_get(&self, request: GetObjectFluentBuilder) -> GetObjectFluentBuilder {
+        match &self.sse_customer_key {
+            Some(key) => request
+                .sse_customer_algorithm("AES256")
+                .sse_customer_key(&key.key)
+                .sse_customer_key_md5(&key.key_md5),
+            None => request,
+        }
+    }
+}
+
+// Metadata travels in HTTP headers, so free-form values are URL-encoded.
+fn describe(info: ObjectInfo<'_>) -> HashMap<String, String> {
+    let encode = |value: &str| form_urlencoded::byte_serialize(value.as_bytes()).collect();
+    let mut metadata =
+        HashMap::from([(META_SCHEMA_VERSION.to_string(), SCHEMA_VERSION.to_string())]);
+    if let Some(user) = info.user {
+        metadata.insert(META_USER.to_string(), encode(user));
+    }
+    if let Some(device) = info.device {
+        metadata.insert(META_DEVICE.to_string(), encode(device));
+    }
+    if let Some(items) = info.items {
+        metadata.insert(META_ITEMS.to_string(), items.to_string());
+    }
+    metadata
+}
+
+fn parse_storage_class(name: &str) -> Result<StorageClass> {
+    let upper = name.trim().to_ascii_uppercase();
+    if !StorageClass::values().contains(&upper.as_str()) {
+        return Err(Error::Config(format!(
+            "unknown storage class {name:?}; expected one of {}",
+            StorageClass::values().join(", ")
+        )));
+    }
+    match StorageClass::from(upper.as_str()) {
+        // Archived objects must be restored before every read or merge.
+        StorageClass::Glacier | StorageClass::DeepArchive => Err(Error::Config(format!(
+            "storage class {upper} cannot be read without a restore"
+        ))),
+        class => Ok(class),
+    }
+}
+
+fn parse_tags(spec: &str) -> Result<String> {
+    let mut tagging = form_urlencoded::Serializer::new(String::new());
+    let mut count = 0;
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key, value) = entry.split_once('=').unwrap_or((entry, ""));
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty() || key.len() > MAX_TAG_KEY_LEN || value.len() > MAX_TAG_VALUE_LEN {
+            return Err(Error::Config(format!(
+                "object tag {entry:?}: expected key=value with a key of 1-{MAX_TAG_KEY_LEN} and a value of at most {MAX_TAG_VALUE_LEN} characters"
+            )));
+        }
+        tagging.append_pair(key, value);
+        count += 1;
+    }
+    if count > MAX_TAGS {
+        return Err(Error::Config(format!(
+            "at most {MAX_TAGS} object tags are allowed, got {count}"
+        )));
+    }
+    Ok(tagging.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_storage_classes_case_insensitively() {
+        assert_eq!(
+            parse_storage_class(" standard_ia ").unwrap(),
+            StorageClass::StandardIa
+        );
+        assert_eq!(
+            parse_storage_class("INTELLIGENT_TIERING").unwrap(),
+            StorageClass::IntelligentTiering
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_archived_storage_classes() {
+        assert!(parse_storage_class("FAST").is_err());
+        assert!(parse_storage_class("GLACIER").is_err());
+        assert!(parse_storage_class("deep_archive").is_err());
+    }
+
+    #[test]
+    fn encodes_tags() {
+        assert_eq!(
+            parse_tags("team=health, env = prod ,,flag").unwrap(),
+            "team=health&env=prod&flag="
+        );
+        assert_eq!(parse_tags("path=a/b c").unwrap(), "path=a%2Fb+c");
+        assert_eq!(parse_tags("").unwrap(), "");
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        assert!(parse_tags("=value").is_err());
+        assert!(parse_tags(&format!("{}=v", "k".repeat(MAX_TAG_KEY_LEN + 1))).is_err());
+        assert!(parse_tags(&format!("k={}", "v".repeat(MAX_TAG_VALUE_LEN + 1))).is_err());
+        let eleven = (0..=MAX_TAGS)
+            .map(|i| format!("k{i}=v"))
+            .collect::<Vec<_>>();
+        assert!(parse_tags(&eleven.join(",")).is_err());
+    }
+
+    #[test]
+    fn describes_objects_with_encoded_values() {
+        let metadata = describe(ObjectInfo {
+            user: Some("bob smith"),
+            device: Some("watch/1"),
+            items: Some(3),
+        });
+        assert_eq!(metadata[META_SCHEMA_VERSION], SCHEMA_VERSION);
+        assert_eq!(metadata[META_USER], "bob+smith");
+        assert_eq!(metadata[META_DEVICE], "watch%2F1");
+        assert_eq!(metadata[META_ITEMS], "3");
+
+        let metadata = describe(ObjectInfo::default());
+        assert_eq!(metadata.len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use serde_json::{Map, Value as JsonValue, json};
+use uuid::Uuid;
+
+use crate::items::{self, BLOOD_PRESSURE_DIASTOLIC, BLOOD_PRESSURE_SYSTOLIC, Reading, Sample};
+
+const OMH_NAMESPACE: &str = "omh";
+
+// Schema wrapping items that have no Open mHealth equivalent, so storage stays lossless.
+const RAW_NAMESPACE: &str = "ahe";
+const RAW_SCHEMA: &str = "raw-item";
+const RAW_VERSION: &str = "1.0";
+
+const BLOOD_PRESSURE_SCHEMA: &str = "blood-pressure";
+const BLOOD_PRESSURE_VERSION: &str = "2.0";
+const SYSTOLIC_FIELD: &str = "systolic_blood_pressure";
+const DIASTOLIC_FIELD: &str = "diastolic_blood_pressure";
+
+struct Schema {
+    metric: &'static str,
+    name: &'static str,
+    version: &'static str,
+    field: &'static str,
+    unit: &'static str,
+}
+
+// Single-value Open mHealth schemas and the metric each one carries.
+const SCHEMAS: &[Schema] = &[
+    Schema {
+        metric: "heart_rate",
+        name: "heart-rate",
+        version: "2.0",
+        field: "heart_rate",
+        unit: "beats/min",
+    },
+    Schema {
+        metric: "step_count",
+        name: "step-count",
+        version: "2.0",
+        field: "step_count",
+        unit: "steps",
+    },
+    Schema {
+        metric: "oxygen_saturation",
+        name: "oxygen-saturation",
+        version: "2.0",
+        field: "oxygen_saturation",
+        unit: "%",
+    },
+    Schema {
+        metric: "body_mass",
+        name: "body-weight",
+        version: "2.0",
+        field: "body_weight",
+        unit: "kg",
+    },
+];
+
+/// Render one stored item as Open mHealth data points (header, schema id, body).
+///
+/// Items that already are data points are returned unchanged. With `keep_unmapped`,
+/// an item without any mappable sample is wrapped in an `ahe:raw-item` data point
+/// instead of being dropped.
+pub fn data_points(device_name: &str, item: &JsonValue, keep_unmapped: bool) -> Vec<JsonValue> {
+    if item.get("header").is_some() && item.get("body").is_some() {
+        return vec![item.clone()];
+    }
+    let points: Vec<JsonValue> = items::readings(item)
+        .iter()
+        .filter_map(|reading| match reading {
+            Reading::Single(sample) => single_value_point(device_name, sample),
+            Reading::BloodPressure {
+                systolic,
+                diastolic,
+            } => blood_pressure_point(device_name, systolic, diastolic),
+        })
+        .collect();
+    if points.is_empty() && keep_unmapped {
+        return vec![data_point(
+            device_name,
+            (RAW_NAMESPACE, RAW_SCHEMA, RAW_VERSION),
+            None,
+            item.clone(),
+        )];
+    }
+    points
+}
+
+/// Convert an ingest payload into the Open mHealth storage format.
+pub fn convert_payload(device_name: &str, payload: JsonValue) -> JsonValue {
+    let items = match payload {
+        JsonValue::Array(items) => items,
+        other => vec![other],
+    };
+    items
+        .iter()
+        .flat_map(|item| data_points(device_name, item, true))
+        .collect()
+}
+
+/// Samples carried by a stored data point, or `None` if `obj` is not a data point.
+pub fn samples(obj: &Map<String, JsonValue>) -> Option<Vec<Sample>> {
+    let header = obj.get("header")?;
+    let body = obj.get("body")?;
+    let schema = header.get("schema_id")?;
+    let name = schema.get("name")?.as_str()?;
+    if schema.get("namespace").and_then(JsonValue::as_str) == Some(RAW_NAMESPACE) {
+        return Some(items::samples(body));
+    }
+
+    let Some((start, end)) = body.get("effective_time_frame").and_then(time_frame_bounds) else {
+        return Some(Vec::new());
+    };
+    let fields: Vec<(&str, &str)> = if name == BLOOD_PRESSURE_SCHEMA {
+        vec![
+            (BLOOD_PRESSURE_SYSTOLIC, SYSTOLIC_FIELD),
+            (BLOOD_PRESSURE_DIASTOLIC, DIASTOLIC_FIELD),
+        ]
+    } else {
+        SCHEMAS
+            .iter()
+            .filter(|s| s.name == name)
+            .map(|s| (s.metric, s.field))
+            .collect()
+    };
+    let source = header
+        .pointer("/acquisition_provenance/source_name")
+        .and_then(JsonValue::as_str);
+
+    Some(
+        fields
+            .into_iter()
+            .filter_map(|(metric, field)| {
+                let quantity = body.get(field)?;
+                Some(Sample {
+                    metric: metric.to_string(),
+                    value: items::json_number(quantity.get("value")?)?,
+                    unit: quantity
+                        .get("unit")
+                        .and_then(JsonValue::as_str)
+                        .map(str::to_string),
+                    start,
+                    end,
+                    source: source.map(str::to_string),
+                    stats: None,
+                })
+            })
+            .collect(),
+    )
+}
+
+fn single_value_point(device_name: &str, sample: &Sample) -> Option<JsonValue> {
+    let schema = SCHEMAS.iter().find(|s| s.metric == sample.metric)?;
+    let mut body = Map::new();
+    body.insert(
+        schema.field.to_string(),
+        json!({ "value": items::standard_value(sample)?, "unit": schema.unit }),
+    );
+    body.insert("effective_time_frame".to_string(), time_frame(sample));
+    Some(data_point(
+        device_name,
+        (OMH_NAMESPACE, schema.name, schema.version),
+        sample.source.as_deref(),
+        JsonValue::Object(body),
+    ))
+}
+
+fn blood_pressure_point(
+    device_name: &str,
+    systolic: &Sample,
+    diastolic: &Sample,
+) -> Option<JsonValue> {
+    let body = json!({
+        SYSTOLIC_FIELD: { "value": items::standard_value(systolic)?, "unit": "mmHg" },
+        DIASTOLIC_FIELD: { "value": items::standard_value(diastolic)?, "unit": "mmHg" },
+        "effective_time_frame": time_frame(systolic),
+    });
+    Some(data_point(
+        device_name,
+        (OMH_NAMESPACE, BLOOD_PRESSURE_SCHEMA, BLOOD_PRESSURE_VERSION),
+        systolic.source.as_deref(),
+        body,
+    ))
+}
+
+// The uuid is derived from device, schema and body, so re-rendering is stable.
+fn data_point(
+    device_name: &str,
+    (namespace, name, version): (&str, &str, &str),
+    source: Option<&str>,
+    body: JsonValue,
+) -> JsonValue {
+    let id = Uuid::new_v5(
+        &Uuid::NAMESPACE_OID,
+        format!("{device_name}|{namespace}:{name}|{body}").as_bytes(),
+    );
+    json!({
+        "header": {
+            "uuid": id.to_string(),
+            "schema_id": { "namespace": namespace, "name": name, "version": version },
+            "creation_date_time": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "acquisition_provenance": { "source_name": source.unwrap_or(device_name) },
+        },
+        "body": body,
+    })
+}
+
+fn time_frame(sample: &Sample) -> JsonValue {
+    let start = sample.start.to_rfc3339_opts(SecondsFormat::Secs, true);
+    match sample.end.filter(|end| *end != sample.start) {
+        Some(end) => json!({
+            "time_interval": {
+                "start_date_time": start,
+                "end_date_time": end.to_rfc3339_opts(SecondsFormat::Secs, true),
+            },
+        }),
+        None => json!({ "date_time": start }),
+    }
+}
+
+fn time_frame_bounds(
+    frame: &JsonValue,
+) -> Option<(DateTime<FixedOffset>, Option<DateTime<FixedOffset>>)> {
+    if let Some(at) = frame.get("date_time").and_then(JsonValue::as_str) {
+        return Some((items::parse_timestamp(at)?, None));
+    }
+    let interval = frame.get("time_interval")?;
+    let start = items::parse_timestamp(interval.get("start_date_time")?.as_str()?)?;
+    let end = interval
+        .get("end_date_time")
+        .and_then(JsonValue::as_str)
+        .and_then(items::parse_timestamp);
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heart_rate() -> JsonValue {
+        json!({
+            "name": "heart_rate",
+            "units": "count/min",
+            "data": [{ "date": "2026-09-01 08:00:00 +0000", "qty": 61, "source": "Watch" }],
+        })
+    }
+
+    #[test]
+    fn renders_single_value_points() {
+        let points = data_points("phone", &heart_rate(), false);
+        assert_eq!(points.len(), 1);
+        let point = &points[0];
+        assert_eq!(point["header"]["schema_id"]["name"], "heart-rate");
+        assert_eq!(
+            point["header"]["acquisition_provenance"]["source_name"],
+            "Watch"
+        );
+        assert_eq!(point["body"]["heart_rate"]["value"], 61.0);
+        assert_eq!(
+            point["body"]["effective_time_frame"]["date_time"],
+            "2026-09-01T08:00:00Z"
+        );
+    }
+
+    #[test]
+    fn uuid_is_stable_per_device() {
+        let first = data_points("phone", &heart_rate(), false);
+        let again = data_points("phone", &heart_rate(), false);
+        let other = data_points("watch", &heart_rate(), false);
+        assert_eq!(first[0]["header"]["uuid"], again[0]["header"]["uuid"]);
+        assert_ne!(first[0]["header"]["uuid"], other[0]["header"]["uuid"]);
+    }
+
+    #[test]
+    fn pairs_blood_pressure() {
+        let item = json!({
+            "name": "blood_pressure",
+            "units": "mmHg",
+            "data": [{ "date": "2026-09-01 08:00:00 +0000", "systolic": 120, "diastolic": 80 }],
+        });
+        let points = data_points("phone", &item, false);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0]["body"][SYSTOLIC_FIELD]["value"], 120.0);
+        assert_eq!(points[0]["body"][DIASTOLIC_FIELD]["value"], 80.0);
+    }
+
+    #[test]
+    fn keeps_unmapped_items_as_raw_points() {
+        let item = json!({ "name": "mindful_minutes", "data": [] });
+        assert!(data_points("phone", &item, false).is_empty());
+        let points = data_points("phone", &item, true);
+        assert_eq!(points[0]["header"]["schema_id"]["namespace"], RAW_NAMESPACE);
+        assert_eq!(points[0]["body"], item);
+    }
+
+    #[test]
+    fn stored_points_round_trip_to_samples() {
+        let stored = convert_payload("phone", JsonValue::Array(vec![heart_rate()]));
+        let point = &stored.as_array().unwrap()[0];
+        assert_eq!(data_points("phone", point, true), vec![point.clone()]);
+
+        let read = samples(point.as_object().unwrap()).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].metric, "heart_rate");
+        assert_eq!(read[0].value, 61.0);
+        assert_eq!(read[0].source.as_deref(), Some("Watch"));
+        assert!(samples(json!({ "name": "x" }).as_object().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDate, Utc};
+
+// Idle buckets are dropped once this many clients are tracked.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client key (user name or IP address).
+pub struct RateLimiter {
+    rate_per_sec: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate_per_sec: f64, burst: u32) -> Self {
+        Self {
+            rate_per_sec,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take one token for `key`, or return how long until one is available.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        if buckets.len() >= PRUNE_THRESHOLD {
+            let (rate, burst) = (self.rate_per_sec, self.burst);
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate_per_sec).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / self.rate_per_sec,
+        ))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Usage {
+    day: NaiveDate,
+    bytes: u64,
+    items: u64,
+}
+
+/// Daily byte and item allowances per client key, reset at midnight UTC.
+pub struct Quotas {
+    max_bytes: Option<u64>,
+    max_items: Option<u64>,
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl Quotas {
+    pub fn new(max_bytes: Option<u64>, max_items: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            max_items,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record `bytes` and `items` for `key` if they fit in today's quota; otherwise
+    /// leave usage unchanged and return the time until the quota resets.
+    pub fn charge(&self, key: &str, bytes: u64, items: u64) -> Result<(), Duration> {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let mut usage = self.usage.lock().expect("quota lock poisoned");
+        usage.retain(|_, u| u.day == today);
+        let entry = usage.entry(key.to_string()).or_insert(Usage {
+            day: today,
+            bytes: 0,
+            items: 0,
+        });
+        let over_bytes = self.max_bytes.is_some_and(|max| entry.bytes + bytes > max);
+        let over_items = self.max_items.is_some_and(|max| entry.items + items > max);
+        if over_bytes || over_items {
+            let midnight = today
+                .succ_opt()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|t| t.and_utc());
+            let wait = midnight
+                .and_then(|m| (m - now).to_std().ok())
+                .unwrap_or(Duration::from_secs(60));
+            return Err(wait);
+        }
+        entry.bytes += bytes;
+        entry.items += items;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_a_burst_then_refills() {
+        let limiter = RateLimiter::new(50.0, 3);
+        for _ in 0..3 {
+            assert!(limiter.check("alice").is_ok());
+        }
+        let wait = limiter.check("alice").unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(20));
+        // Other clients have buckets of their own.
+        assert!(limiter.check("bob").is_ok());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(limiter.check("alice").is_ok());
+    }
+
+    #[test]
+    fn quotas_reject_without_charging() {
+        let quotas = Quotas::new(Some(100), Some(3));
+        assert!(quotas.charge("alice", 60, 1).is_ok());
+        let wait = quotas.charge("alice", 60, 1).unwrap_err();
+        assert!(wait <= Duration::from_secs(24 * 60 * 60));
+        assert!(quotas.charge("alice", 40, 2).is_ok());
+        assert!(quotas.charge("alice", 0, 1).is_err());
+        assert!(quotas.charge("bob", 100, 3).is_ok());
+
+        let unlimited = Quotas::new(None, None);
+        assert!(unlimited.charge("alice", u64::MAX / 2, 1_000_000).is_ok());
+    }
+}
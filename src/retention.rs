@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{FixedOffset, Utc};
+use serde_json::Value as JsonValue;
+use tracing::{debug, error, info, instrument};
+
+use crate::aggregate::{Aggregator, DOWNSAMPLED_FIELD, Interval, downsampled_items};
+use crate::error::Result;
+use crate::items;
+use crate::maintenance::{self, DayFile, Tier};
+use crate::metrics;
+use crate::s3;
+use crate::state::AppState;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    All,
+    /// A device, by its stored (sanitized) name.
+    Device(String),
+    /// A metric, by its canonical snake_case name.
+    Metric(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Delete,
+    /// Replace raw samples with hourly aggregates.
+    Downsample,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub target: Target,
+    pub max_age_days: u32,
+    pub action: Action,
+}
+
+/// Retention rules from `AHE_RETENTION`, e.g.
+/// `*=3650,metric:heart_rate=90:downsample,device:old-phone=30`.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    rules: Vec<Rule>,
+}
+
+// What to do with one day file.
+#[derive(Debug, Default)]
+struct Plan {
+    delete_file: bool,
+    downsample_all: bool,
+    delete_metrics: Vec<String>,
+    downsample_metrics: Vec<String>,
+}
+
+impl Plan {
+    fn is_empty(&self) -> bool {
+        !self.delete_file
+            && !self.downsample_all
+            && self.delete_metrics.is_empty()
+            && self.downsample_metrics.is_empty()
+    }
+}
+
+/// Outcome of one sweep over the bucket.
+#[derive(Debug, Default)]
+pub struct SweepReport {
+    pub files_deleted: u64,
+    pub files_rewritten: u64,
+    pub items_deleted: u64,
+    pub items_downsampled: u64,
+}
+
+impl RetentionPolicy {
+    pub fn parse(spec: &str) -> std::result::Result<Self, String> {
+        let rules = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(parse_rule)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if rules.is_empty() {
+            return Err("retention policy has no rules".to_string());
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    fn plan(&self, device: &str, age_days: i64) -> Plan {
+        let mut plan = Plan::default();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|r| age_days > i64::from(r.max_age_days))
+        {
+            match (&rule.target, rule.action) {
+                (Target::Device(d), _) if d != device => {}
+                (Target::All | Target::Device(_), Action::Delete) => plan.delete_file = true,
+                (Target::All | Target::Device(_), Action::Downsample) => plan.downsample_all = true,
+                (Target::Metric(m), Action::Delete) => plan.delete_metrics.push(m.clone()),
+                (Target::Metric(m), Action::Downsample) => plan.downsample_metrics.push(m.clone()),
+            }
+        }
+        plan
+    }
+}
+
+fn parse_rule(rule: &str) -> std::result::Result<Rule, String> {
+    let (target, rest) = rule
+        .split_once('=')
+        .ok_or_else(|| format!("retention rule {rule:?}: expected <target>=<days>[:<action>]"))?;
+    let target = match target.trim() {
+        "*" => Target::All,
+        t => match t.split_once(':') {
+            Some(("device", name)) if !name.is_empty() => {
+                Target::Device(s3::sanitize_path_segment(name))
+            }
+            Some(("metric", name)) if !name.is_empty() => {
+                Target::Metric(items::canonical_metric(name))
+            }
+            _ => {
+                return Err(format!(
+                    "retention rule {rule:?}: target must be *, device:<name> or metric:<name>"
+                ));
+            }
+        },
+    };
+    let (days, action) = rest.split_once(':').unwrap_or((rest, "delete"));
+    let max_age_days = match days.trim().parse::<u32>() {
+        Ok(days) if days > 0 => days,
+        _ => {
+            return Err(format!(
+                "retention rule {rule:?}: days must be a positive integer"
+            ));
+        }
+    };
+    let action = match action.trim() {
+        "delete" => Action::Delete,
+        "downsample" => Action::Downsample,
+        other => {
+            return Err(format!(
+                "retention rule {rule:?}: unknown action {other:?} (delete or downsample)"
+            ));
+        }
+    };
+    Ok(Rule {
+        target,
+        max_age_days,
+        action,
+    })
+}
+
+/// Run a sweep every `interval` until the process exits.
+pub fn spawn_sweeper(state: AppState, interval: Duration) -> tokio::task::JoinHandle<()> {
+    maintenance::spawn_periodic(state, interval, |state| async move {
+        match sweep(&state).await {
+            Ok(report) => info!(
+                event = "retention_sweep",
+                files_deleted = report.files_deleted,
+                files_rewritten = report.files_rewritten,
+                items_deleted = report.items_deleted,
+                items_downsampled = report.items_downsampled,
+                "retention sweep finished"
+            ),
+            Err(err) => error!(error = ?err, "retention sweep failed"),
+        }
+    })
+}
+
+/// Apply the retention policy to every day file under the prefix, for all users, and
+/// to their rollup tier files.
+#[instrument(skip(state))]
+pub async fn sweep(state: &AppState) -> Result<SweepReport> {
+    let mut report = SweepReport::default();
+    let Some(policy) = state.retention.as_deref() else {
+        return Ok(report);
+    };
+    let today = Utc::now().date_naive();
+    let (_guard, days) = maintenance::day_files(state).await?;
+    for day in days {
+        let plan = policy.plan(&day.device, (today - day.date).num_days());
+        if plan.is_empty() {
+            continue;
+        }
+        if let Err(err) = apply(state, &day, &plan, &mut report).await {
+            maintenance::log_failure("retention", &day.key, &err);
+        }
+    }
+    Ok(report)
+}
+
+async fn apply(
+    state: &AppState,
+    day: &DayFile,
+    plan: &Plan,
+    report: &mut SweepReport,
+) -> Result<()> {
+    let key = day.key.as_str();
+    if plan.delete_file {
+        let mut keys = vec![key.to_string()];
+        if day.tier == Tier::Raw {
+            keys.push(s3::summary_key(key));
+        }
+        let failed = s3::delete_objects(state, &keys).await?;
+        if !failed.contains(&keys[0]) {
+            report.files_deleted += 1;
+            metrics::inc_retention_deleted_files();
+            info!(event = "retention_delete", %key, "deleted expired day file");
+            refresh_day_summary(state, day).await;
+        }
+        return Ok(());
+    }
+
+    let Some((stored, etag)) = s3::load_json_versioned(state, key).await? else {
+        return Ok(());
+    };
+    let (kept, deleted, downsampled) = transform(s3::into_items(Some(stored)), plan);
+    if deleted == 0 && downsampled == 0 {
+        return Ok(());
+    }
+    // A file changed by an ingest or deleted by an erasure since it was read is left
+    // alone; the next sweep picks it up again.
+    let kept = JsonValue::Array(kept);
+    let written = match day.tier {
+        Tier::Raw => s3::store_day_if_unchanged(state, key, &kept, Some(&etag)).await?,
+        Tier::Rollup => s3::put_json_if_unchanged(state, key, &kept, Some(&etag)).await?,
+    };
+    if !written {
+        debug!(%key, "day file changed during retention, skipping");
+        return Ok(());
+    }
+    refresh_day_summary(state, day).await;
+    report.files_rewritten += 1;
+    report.items_deleted += deleted;
+    report.items_downsampled += downsampled;
+    metrics::add_retention_items("delete", deleted);
+    metrics::add_retention_items("downsample", downsampled);
+    info!(
+        event = "retention_rewrite",
+        %key,
+        items_deleted = deleted,
+        items_downsampled = downsampled,
+        "applied retention to day file"
+    );
+    Ok(())
+}
+
+// Day summaries count the rollup tier, so they change along with a rollup file.
+async fn refresh_day_summary(state: &AppState, day: &DayFile) {
+    if day.tier == Tier::Rollup {
+        let day_key =
+            s3::s3_key_for_device_date(&state.prefix, day.user.as_deref(), &day.device, day.date);
+        s3::refresh_summary(state, &day_key).await;
+    }
+}
+
+// Drop items of deleted metrics and replace items of downsampled metrics with hourly
+// aggregates. Returns the new items and the numbers of items deleted and downsampled.
+fn transform(stored: Vec<JsonValue>, plan: &Plan) -> (Vec<JsonValue>, u64, u64) {
+    let utc = FixedOffset::east_opt(0).expect("zero offset");
+    let mut kept = Vec::with_capacity(stored.len());
+    let mut aggregators: BTreeMap<String, Aggregator> = BTreeMap::new();
+    let (mut deleted, mut downsampled) = (0, 0);
+    for item in stored {
+        if item.get(DOWNSAMPLED_FIELD).is_some() {
+            kept.push(item);
+            continue;
+        }
+        let samples = items::samples(&item);
+        let has = |metrics: &[String]| samples.iter().any(|s| metrics.contains(&s.metric));
+        if has(&plan.delete_metrics) {
+            deleted += 1;
+        } else if !samples.is_empty() && (plan.downsample_all || has(&plan.downsample_metrics)) {
+            downsampled += 1;
+            for sample in &samples {
+                aggregators
+                    .entry(sample.metric.clone())
+                    .or_insert_with(|| Aggregator::new(Interval::Hour, utc))
+                    .add(sample);
+            }
+        } else {
+            kept.push(item);
+        }
+    }
+    for (metric, aggregator) in &aggregators {
+        kept.extend(downsampled_items(metric, Interval::Hour, aggregator));
+    }
+    debug!(deleted, downsampled, "transformed day file");
+    (kept, deleted, downsampled)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parses_targets_and_actions() {
+        let policy =
+            RetentionPolicy::parse("*=3650, metric:HeartRate=90:downsample,device:Old Phone=30")
+                .unwrap();
+        let rules = policy.rules();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].target, Target::All);
+        assert_eq!(rules[0].action, Action::Delete);
+        assert_eq!(rules[1].target, Target::Metric("heart_rate".to_string()));
+        assert_eq!(rules[1].max_age_days, 90);
+        assert_eq!(rules[1].action, Action::Downsample);
+        assert_eq!(
+            rules[2].target,
+            Target::Device(s3::sanitize_path_segment("Old Phone"))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for spec in [
+            "",
+            "*",
+            "*=0",
+            "*=-1",
+            "*=30:archive",
+            "user:alice=30",
+            "device:=30",
+        ] {
+            assert!(RetentionPolicy::parse(spec).is_err(), "{spec:?}");
+        }
+    }
+
+    #[test]
+    fn plans_rules_older_than_their_age() {
+        let policy =
+            RetentionPolicy::parse("*=365,metric:heart_rate=90:downsample,device:old=30").unwrap();
+
+        assert!(policy.plan("phone", 90).is_empty());
+
+        let plan = policy.plan("phone", 91);
+        assert_eq!(plan.downsample_metrics, ["heart_rate"]);
+        assert!(!plan.delete_file);
+
+        let plan = policy.plan("old", 31);
+        assert!(plan.delete_file);
+        assert!(policy.plan("phone", 366).delete_file);
+    }
+
+    #[test]
+    fn plans_device_downsampling_and_metric_deletion() {
+        let policy =
+            RetentionPolicy::parse("device:watch=7:downsample,metric:step_count=14").unwrap();
+        let plan = policy.plan("watch", 8);
+        assert!(plan.downsample_all && plan.delete_metrics.is_empty());
+        let plan = policy.plan("phone", 15);
+        assert!(!plan.downsample_all);
+        assert_eq!(plan.delete_metrics, ["step_count"]);
+    }
+
+    #[test]
+    fn downsamples_into_hourly_items_and_deletes_metrics() {
+        let stored = vec![
+            json!({"type": "HeartRate", "bpm": 60, "ts": "2026-09-01T08:10:00Z"}),
+            json!({"type": "HeartRate", "bpm": 80, "ts": "2026-09-01T08:50:00Z"}),
+            json!({"type": "StepCount", "count": 12, "ts": "2026-09-01T08:00:00Z"}),
+            json!({"note": "no samples"}),
+        ];
+        let plan = Plan {
+            downsample_metrics: vec!["heart_rate".to_string()],
+            delete_metrics: vec!["step_count".to_string()],
+            ..Plan::default()
+        };
+        let (kept, deleted, downsampled) = transform(stored, &plan);
+        assert_eq!((deleted, downsampled), (1, 2));
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0]["note"], "no samples");
+        let hour = &kept[1];
+        assert_eq!(hour[DOWNSAMPLED_FIELD], "hour");
+        assert_eq!(hour["start"], "2026-09-01T08:00:00+00:00");
+        assert_eq!(
+            (hour["value"].as_f64(), hour["count"].as_u64()),
+            (Some(70.0), Some(2))
+        );
+
+        // Downsampled items are kept as they are by later sweeps.
+        let (again, deleted, downsampled) = transform(kept.clone(), &plan);
+        assert_eq!((deleted, downsampled), (0, 0));
+        assert_eq!(again, kept);
+    }
+}
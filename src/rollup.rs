@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+use chrono::{FixedOffset, Utc};
+use serde_json::{Value as JsonValue, json};
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, instrument};
+
+use crate::aggregate::{Aggregator, DOWNSAMPLED_FIELD, Interval, downsampled_items};
+use crate::error::Result;
+use crate::items::{self, Sample};
+use crate::maintenance::{self, Tier};
+use crate::metrics;
+use crate::s3;
+use crate::state::AppState;
+
+/// Field of the item in a rollup file listing the ids of the raw items rolled up into
+/// it, so they are never counted twice.
+pub const ROLLED_UP_FIELD: &str = "rolled_up_items";
+
+// Bytes of the item digest kept in an item id.
+const ITEM_ID_BYTES: usize = 8;
+
+/// Metrics whose raw samples move to the rollup tier as aggregates once a day file is
+/// older than `after_days`, from `AHE_ROLLUP`, e.g. `heart_rate=minute,respiratory_rate=five_minutes`.
+#[derive(Debug, Clone)]
+pub struct RollupPolicy {
+    metrics: Vec<(String, Interval)>,
+    after_days: u32,
+}
+
+/// Outcome of one rollup run.
+#[derive(Debug, Default)]
+pub struct RollupReport {
+    pub files_rolled_up: u64,
+    pub items_moved: u64,
+    pub aggregates_written: u64,
+}
+
+impl RollupPolicy {
+    pub fn parse(spec: &str, after_days: u32) -> std::result::Result<Self, String> {
+        let mut metrics = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let interval = entry.split_once('=').and_then(|(metric, interval)| {
+                let interval = Interval::parse(interval.trim())
+                    .filter(|i| matches!(i, Interval::Minute | Interval::FiveMinutes))?;
+                Some((items::canonical_metric(metric.trim()), interval))
+            });
+            match interval {
+                Some(rule) => metrics.push(rule),
+                None => {
+                    return Err(format!(
+                        "rollup entry {entry:?}: expected <metric>=minute or <metric>=five_minutes"
+                    ));
+                }
+            }
+        }
+        if metrics.is_empty() {
+            return Err("rollup policy has no metrics".to_string());
+        }
+        Ok(Self {
+            metrics,
+            after_days,
+        })
+    }
+
+    pub fn metrics(&self) -> &[(String, Interval)] {
+        &self.metrics
+    }
+
+    fn interval_for(&self, metric: &str) -> Option<Interval> {
+        self.metrics
+            .iter()
+            .find(|(m, _)| m == metric)
+            .map(|(_, interval)| *interval)
+    }
+}
+
+/// Run the rollup every `interval` until the process exits.
+pub fn spawn_rollup(state: AppState, interval: Duration) -> tokio::task::JoinHandle<()> {
+    maintenance::spawn_periodic(state, interval, |state| async move {
+        match roll_up(&state).await {
+            Ok(report) => info!(
+                event = "rollup_run",
+                files_rolled_up = report.files_rolled_up,
+                items_moved = report.items_moved,
+                aggregates_written = report.aggregates_written,
+                "rollup finished"
+            ),
+            Err(err) => error!(error = ?err, "rollup failed"),
+        }
+    })
+}
+
+/// Move raw samples of the configured metrics out of every day file older than the
+/// policy's age, for all users.
+#[instrument(skip(state))]
+pub async fn roll_up(state: &AppState) -> Result<RollupReport> {
+    let mut report = RollupReport::default();
+    let Some(policy) = state.rollup.as_deref() else {
+        return Ok(report);
+    };
+    let today = Utc::now().date_naive();
+    let (_guard, days) = maintenance::day_files(state).await?;
+    for day in days {
+        if day.tier != Tier::Raw || (today - day.date).num_days() <= i64::from(policy.after_days) {
+            continue;
+        }
+        let rollup_key = s3::rollup_key(&state.prefix, day.user.as_deref(), &day.device, day.date);
+        if let Err(err) = roll_up_day(state, policy, &day.key, &rollup_key, &mut report).await {
+            maintenance::log_failure("rollup", &day.key, &err);
+        }
+    }
+    Ok(report)
+}
+
+/// Whether a stored item is the record of rolled-up raw items kept in a rollup file.
+pub fn is_record(item: &JsonValue) -> bool {
+    item.get(ROLLED_UP_FIELD).is_some()
+}
+
+/// Contents of a rollup tier file: aggregates keyed by metric and interval, the ids of
+/// the raw items they were built from, and any other items, kept as they are.
+#[derive(Default)]
+struct RollupFile {
+    aggregators: BTreeMap<(String, Interval), Aggregator>,
+    rolled_up: BTreeSet<String>,
+    other: Vec<JsonValue>,
+}
+
+impl RollupFile {
+    fn parse(stored: Vec<JsonValue>) -> Self {
+        let mut file = Self::default();
+        for item in stored {
+            if let Some(ids) = item.get(ROLLED_UP_FIELD).and_then(JsonValue::as_array) {
+                file.rolled_up
+                    .extend(ids.iter().filter_map(JsonValue::as_str).map(str::to_string));
+                continue;
+            }
+            let Some(interval) = item
+                .get(DOWNSAMPLED_FIELD)
+                .and_then(JsonValue::as_str)
+                .and_then(Interval::parse)
+            else {
+                file.other.push(item);
+                continue;
+            };
+            for sample in items::samples(&item) {
+                file.add(interval, &sample);
+            }
+        }
+        file
+    }
+
+    // Aggregates of one bucket are merged, so a bucket appears once however many runs
+    // contributed to it.
+    fn add(&mut self, interval: Interval, sample: &Sample) {
+        let utc = FixedOffset::east_opt(0).expect("zero offset");
+        self.aggregators
+            .entry((sample.metric.clone(), interval))
+            .or_insert_with(|| Aggregator::new(interval, utc))
+            .add(sample);
+    }
+
+    fn aggregates(&self) -> usize {
+        self.aggregators.values().map(|a| a.buckets().count()).sum()
+    }
+
+    fn into_json(self) -> JsonValue {
+        let mut items = self.other;
+        for ((metric, interval), aggregator) in &self.aggregators {
+            items.extend(downsampled_items(metric, *interval, aggregator));
+        }
+        items.push(json!({ ROLLED_UP_FIELD: self.rolled_up }));
+        JsonValue::Array(items)
+    }
+}
+
+/// Merge two rollup tier files of the same day, e.g. when moving one onto the other.
+pub fn merge_files(existing: JsonValue, incoming: JsonValue) -> JsonValue {
+    let mut items = s3::into_items(Some(existing));
+    items.extend(s3::into_items(Some(incoming)));
+    RollupFile::parse(items).into_json()
+}
+
+// Ids of stored items: a digest of the item, numbered among identical items so that
+// repeated samples stay distinct.
+fn item_ids(stored: &[JsonValue]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    stored
+        .iter()
+        .map(|item| {
+            let bytes = serde_json::to_vec(item).unwrap_or_default();
+            let digest = hex::encode(&Sha256::digest(&bytes)[..ITEM_ID_BYTES]);
+            let n = seen.entry(digest.clone()).or_default();
+            *n += 1;
+            match *n {
+                1 => digest,
+                n => format!("{digest}-{n}"),
+            }
+        })
+        .collect()
+}
+
+// Move the rolled-up items of one day file into its rollup file. Both files are written
+// only if unchanged since they were read, and the rollup file records the ids of the
+// items it holds, so a run interrupted between the two writes is completed by the next
+// one without counting any item twice.
+async fn roll_up_day(
+    state: &AppState,
+    policy: &RollupPolicy,
+    key: &str,
+    rollup_key: &str,
+    report: &mut RollupReport,
+) -> Result<()> {
+    let Some((day, day_etag)) = s3::load_json_versioned(state, key).await? else {
+        return Ok(());
+    };
+    let stored = s3::into_items(Some(day));
+    let ids = item_ids(&stored);
+
+    let (existing, rollup_etag) = s3::load_json_versioned(state, rollup_key).await?.unzip();
+    let mut rollup = RollupFile::parse(s3::into_items(existing));
+    let mut kept = Vec::with_capacity(stored.len());
+    let (mut moved, mut added) = (0, 0);
+    for (item, id) in stored.into_iter().zip(ids) {
+        let samples = items::samples(&item);
+        let rolled = item.get(DOWNSAMPLED_FIELD).is_none()
+            && samples
+                .iter()
+                .any(|s| policy.interval_for(&s.metric).is_some());
+        if !rolled {
+            kept.push(item);
+            continue;
+        }
+        moved += 1;
+        if !rollup.rolled_up.insert(id) {
+            // Already in the rollup file from a run that did not get to rewrite the day.
+            continue;
+        }
+        added += 1;
+        // Other metrics in the same item (e.g. the diastolic half of a blood pressure
+        // pair) move along, at the finest interval unless configured otherwise.
+        for sample in &samples {
+            let interval = policy
+                .interval_for(&sample.metric)
+                .unwrap_or(Interval::Minute);
+            rollup.add(interval, sample);
+        }
+    }
+    if moved == 0 {
+        return Ok(());
+    }
+
+    // Write the rollup tier first: a failure before the day file is rewritten leaves
+    // the items in both files, and the next run only removes them from the day file.
+    let written = rollup.aggregates() as u64;
+    if added > 0
+        && !s3::put_json_if_unchanged(
+            state,
+            rollup_key,
+            &rollup.into_json(),
+            rollup_etag.as_deref(),
+        )
+        .await?
+    {
+        debug!(%rollup_key, "rollup file changed during the rollup, skipping");
+        return Ok(());
+    }
+    if !s3::store_day_if_unchanged(state, key, &JsonValue::Array(kept), Some(&day_etag)).await? {
+        debug!(%key, "day file changed during the rollup, skipping");
+        return Ok(());
+    }
+
+    report.files_rolled_up += 1;
+    report.items_moved += moved;
+    report.aggregates_written += written;
+    metrics::add_rollup_items(moved);
+    info!(
+        event = "rollup_day",
+        %key,
+        %rollup_key,
+        items_moved = moved,
+        aggregates = written,
+        "moved raw samples to rollup tier"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heart_rate(ts: &str, bpm: f64) -> JsonValue {
+        json!({"type": "HeartRate", "bpm": bpm, "ts": ts})
+    }
+
+    #[test]
+    fn parses_policy() {
+        let policy =
+            RollupPolicy::parse("heart_rate=minute, RespiratoryRate=five_minutes", 30).unwrap();
+        assert_eq!(
+            policy.metrics(),
+            [
+                ("heart_rate".to_string(), Interval::Minute),
+                ("respiratory_rate".to_string(), Interval::FiveMinutes),
+            ]
+        );
+        assert_eq!(policy.interval_for("heart_rate"), Some(Interval::Minute));
+        assert_eq!(policy.interval_for("step_count"), None);
+    }
+
+    #[test]
+    fn rejects_bad_policies() {
+        for spec in [
+            "",
+            " , ",
+            "heart_rate",
+            "heart_rate=hour",
+            "heart_rate=weekly",
+        ] {
+            assert!(RollupPolicy::parse(spec, 30).is_err(), "{spec:?}");
+        }
+    }
+
+    #[test]
+    fn numbers_identical_items() {
+        let a = heart_rate("2026-09-01T10:00:00Z", 60.0);
+        let b = heart_rate("2026-09-01T10:00:30Z", 60.0);
+        let ids = item_ids(&[a.clone(), b, a]);
+        assert_eq!(ids[0].len(), ITEM_ID_BYTES * 2);
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(ids[2], format!("{}-2", ids[0]));
+    }
+
+    #[test]
+    fn rollup_file_round_trips() {
+        let mut file = RollupFile::default();
+        for (ts, bpm) in [
+            ("2026-09-01T10:00:10Z", 60.0),
+            ("2026-09-01T10:00:40Z", 80.0),
+        ] {
+            for sample in items::samples(&heart_rate(ts, bpm)) {
+                file.add(Interval::Minute, &sample);
+            }
+        }
+        file.rolled_up.extend(["a".to_string(), "b".to_string()]);
+        file.other.push(json!({"note": "kept"}));
+        assert_eq!(file.aggregates(), 1);
+
+        let stored = s3::into_items(Some(file.into_json()));
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0], json!({"note": "kept"}));
+        assert_eq!(stored[1]["count"], 2);
+        assert_eq!(stored[1]["value"], 70.0);
+        assert!(is_record(&stored[2]));
+
+        let parsed = RollupFile::parse(stored);
+        assert_eq!(parsed.aggregates(), 1);
+        assert_eq!(parsed.rolled_up.len(), 2);
+        assert_eq!(parsed.other.len(), 1);
+    }
+
+    #[test]
+    fn merging_files_merges_buckets_and_ids() {
+        let file = |ts: &str, bpm: f64, id: &str| {
+            let mut file = RollupFile::default();
+            for sample in items::samples(&heart_rate(ts, bpm)) {
+                file.add(Interval::Minute, &sample);
+            }
+            file.rolled_up.insert(id.to_string());
+            file.into_json()
+        };
+        let merged = merge_files(
+            file("2026-09-01T10:00:10Z", 60.0, "a"),
+            file("2026-09-01T10:00:50Z", 90.0, "b"),
+        );
+        let stored = s3::into_items(Some(merged));
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0]["count"], 2);
+        assert_eq!(stored[0]["min"], 60.0);
+        assert_eq!(stored[0]["max"], 90.0);
+        assert_eq!(stored[1][ROLLED_UP_FIELD], json!(["a", "b"]));
+    }
+}
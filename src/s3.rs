@@ -1,17 +1,37 @@
-use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::get_object::{GetObjectError, GetObjectOutput};
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
 use aws_sdk_s3::{error::SdkError, primitives::ByteStream};
+use aws_smithy_types::byte_stream::error::Error as ByteStreamError;
+use aws_smithy_types::date_time::Format as DateTimeFormat;
+use bytes::Bytes;
 use chrono::{Datelike, NaiveDate, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use serde_json::Value as JsonValue;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
+use crate::config::StorageFormat;
+use crate::encryption::{self, Envelope};
 use crate::error::{Error, Result};
 use crate::metrics;
+use crate::normalize;
+use crate::object_options::ObjectInfo;
+use crate::omh;
+use crate::rollup;
 use crate::state::AppState;
+use crate::summary;
 
-#[instrument(skip(prefix, device_name))]
+// Day files fetched concurrently when reading a range of days.
+const DAY_READ_CONCURRENCY: usize = 8;
+/// Directory under a user prefix holding the rollup tier.
+pub const ROLLUP_DIR: &str = "_rollup";
+
+#[instrument(skip(prefix, user, device_name))]
 pub fn s3_key_for_device_date(
     prefix: &Option<String>,
+    user: Option<&str>,
     device_name: &str,
     date: NaiveDate,
 ) -> String {
@@ -22,67 +42,618 @@ pub fn s3_key_for_device_date(
         date.month(),
         date.day()
     );
-    match prefix {
-        Some(p) => format!("{}{}/{}", p, dev, filename),
-        None => format!("{}/{}", dev, filename),
+    format!("{}{}/{}", user_prefix(prefix, user), dev, filename)
+}
+
+/// Key prefix holding all devices of a user: `prefix/<user>/`, or just the
+/// configured prefix when requests are not authenticated.
+pub fn user_prefix(prefix: &Option<String>, user: Option<&str>) -> String {
+    let mut out = prefix.clone().unwrap_or_default();
+    if let Some(user) = user {
+        out.push_str(&sanitize_path_segment(user));
+        out.push('/');
     }
+    out
 }
 
-#[instrument(skip(state, new_json))]
-pub async fn save_or_merge_json(state: &AppState, key: &str, new_json: JsonValue) -> Result<()> {
-    // Try to fetch existing object
-    debug!(%key, "checking existing object");
-    let existing_json = match state
-        .s3
-        .get_object()
-        .bucket(&state.bucket)
-        .key(key)
+#[instrument(skip(state))]
+pub async fn load_json(state: &AppState, key: &str) -> Result<Option<JsonValue>> {
+    let Some(bytes) = load_bytes(state, key).await? else {
+        return Ok(None);
+    };
+    let text = String::from_utf8(bytes.to_vec())?;
+    Ok(Some(serde_json::from_str::<JsonValue>(&text)?))
+}
+
+/// Contents of an object, decrypted if needed, or `None` if it does not exist.
+#[instrument(skip(state))]
+pub async fn load_bytes(state: &AppState, key: &str) -> Result<Option<Bytes>> {
+    match state
+        .object_options
+        .apply_get(state.s3.get_object().bucket(&state.bucket).key(key))
         .send()
         .await
     {
         Ok(obj) => {
-            let bytes = obj.body.collect().await?.into_bytes();
-            let text = String::from_utf8(bytes.to_vec())?;
-            debug!(%key, bytes = text.len(), "existing object found");
-            Some(serde_json::from_str::<JsonValue>(&text)?)
+            let bytes = read_object(state, key, obj).await?;
+            debug!(%key, bytes = bytes.len(), "existing object found");
+            Ok(Some(bytes))
         }
         Err(err) => {
             if is_s3_not_found(&err) {
-                debug!(%key, "no existing object (will create new)");
-                None
+                debug!(%key, "no existing object");
+                Ok(None)
             } else {
                 let b = Box::new(err);
-                return Err(Error::from(b));
+                Err(Error::from(b))
             }
         }
+    }
+}
+
+/// Read a fetched object's body, decrypting it if it was stored encrypted.
+pub async fn read_object(state: &AppState, key: &str, obj: GetObjectOutput) -> Result<Bytes> {
+    let envelope = Envelope::from_metadata(obj.metadata())?;
+    let bytes = obj.body.collect().await?.into_bytes();
+    let Some(envelope) = envelope else {
+        return Ok(bytes);
     };
+    let keyring = state.encryption.as_deref().ok_or_else(|| {
+        Error::Encryption(format!("{key} is encrypted but no key file is configured"))
+    })?;
+    Ok(Bytes::from(keyring.decrypt(key, &bytes, &envelope)?))
+}
+
+/// Whether a fetched object's body must go through [`read_object`] rather than be
+/// forwarded as stored.
+pub fn is_encrypted(obj: &GetObjectOutput) -> bool {
+    obj.metadata()
+        .is_some_and(|m| m.contains_key(encryption::META_SCHEME))
+}
+
+/// Outcome of a conditional object fetch.
+pub enum Fetched {
+    Object(Box<GetObjectOutput>),
+    NotModified,
+    NotFound,
+}
+
+/// Start fetching `key` without reading its body; with `if_none_match`, S3 answers
+/// `NotModified` when the ETag still matches.
+#[instrument(skip(state))]
+pub async fn fetch_object(
+    state: &AppState,
+    key: &str,
+    if_none_match: Option<String>,
+) -> Result<Fetched> {
+    match state
+        .object_options
+        .apply_get(state.s3.get_object().bucket(&state.bucket).key(key))
+        .set_if_none_match(if_none_match)
+        .send()
+        .await
+    {
+        Ok(obj) => Ok(Fetched::Object(Box::new(obj))),
+        Err(err) if is_s3_not_found(&err) => Ok(Fetched::NotFound),
+        Err(err) if err.raw_response().map(|r| r.status().as_u16()) == Some(304) => {
+            Ok(Fetched::NotModified)
+        }
+        Err(err) => Err(Error::from(Box::new(err))),
+    }
+}
+
+/// Adapt an S3 body into a byte stream so responses are forwarded chunk by chunk.
+pub fn body_stream(
+    body: ByteStream,
+) -> impl Stream<Item = std::result::Result<Bytes, ByteStreamError>> + Send + 'static {
+    futures_util::stream::unfold(Some(body), |body| async move {
+        let mut body = body?;
+        match body.try_next().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(body))),
+            Ok(None) => None,
+            // End the stream after reporting the error.
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+}
+
+/// One entry of a bucket listing.
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: i64,
+    /// RFC 3339 timestamp.
+    pub last_modified: Option<String>,
+}
+
+/// List every object under `prefix`, following `ListObjectsV2` continuation tokens.
+/// With `start_after`, only keys sorting after it are returned.
+#[instrument(skip(state))]
+pub async fn list_objects(
+    state: &AppState,
+    prefix: &str,
+    start_after: Option<String>,
+) -> Result<Vec<StoredObject>> {
+    let mut pages = state
+        .s3
+        .list_objects_v2()
+        .bucket(&state.bucket)
+        .prefix(prefix)
+        .set_start_after(start_after)
+        .into_paginator()
+        .send();
+    let mut objects = Vec::new();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(Box::new)?;
+        objects.extend(page.contents().iter().filter_map(|obj| {
+            Some(StoredObject {
+                key: obj.key()?.to_string(),
+                size: obj.size().unwrap_or_default(),
+                last_modified: obj
+                    .last_modified()
+                    .and_then(|t| t.fmt(DateTimeFormat::DateTime).ok()),
+            })
+        }));
+    }
+    debug!(%prefix, objects = objects.len(), "listed objects");
+    Ok(objects)
+}
+
+/// Split a key relative to a user prefix into device and day, accepting only
+/// day files (`<device>/<YYYY-MM-DD>.json`).
+pub fn parse_day_key(relative: &str) -> Option<(&str, NaiveDate)> {
+    let (device, file) = relative.split_once('/')?;
+    if device.is_empty() {
+        return None;
+    }
+    Some((device, parse_day_file(file)?))
+}
+
+/// User, device and date of a day file relative to the configured prefix, stored either
+/// as `<device>/<date>.json` (without auth) or `<user>/<device>/<date>.json`. Keys in
+/// reserved `_` trees (audit log, rollups) are not day files.
+pub fn parse_stored_day(relative: &str) -> Option<(Option<&str>, &str, NaiveDate)> {
+    if relative.starts_with('_') {
+        return None;
+    }
+    if let Some((device, date)) = parse_day_key(relative) {
+        return Some((None, device, date));
+    }
+    let (user, rest) = relative.split_once('/')?;
+    let (device, date) = parse_day_key(rest)?;
+    (!device.starts_with('_')).then_some((Some(user), device, date))
+}
+
+/// User, device and date of a rollup tier file relative to the configured prefix:
+/// `_rollup/<device>/<date>.json` (without auth) or `<user>/_rollup/<device>/<date>.json`.
+pub fn parse_stored_rollup(relative: &str) -> Option<(Option<&str>, &str, NaiveDate)> {
+    if let Some((device, date)) = parse_rollup_key(relative) {
+        return Some((None, device, date));
+    }
+    let (user, rest) = relative.split_once('/')?;
+    let (device, date) = parse_rollup_key(rest)?;
+    (!user.starts_with('_')).then_some((Some(user), device, date))
+}
+
+/// Split a key relative to a user prefix into device and day, accepting only rollup
+/// tier files (`_rollup/<device>/<YYYY-MM-DD>.json`).
+pub fn parse_rollup_key(relative: &str) -> Option<(&str, NaiveDate)> {
+    parse_day_key(relative.strip_prefix(ROLLUP_DIR)?.strip_prefix('/')?)
+}
+
+/// Key of the rollup tier file holding aggregates moved out of a day file:
+/// `prefix/<user>/_rollup/<device>/<YYYY-MM-DD>.json`.
+pub fn rollup_key(
+    prefix: &Option<String>,
+    user: Option<&str>,
+    device_name: &str,
+    date: NaiveDate,
+) -> String {
+    format!(
+        "{}{ROLLUP_DIR}/{}/{date}.json",
+        user_prefix(prefix, user),
+        sanitize_path_segment(device_name)
+    )
+}
+
+/// Date of a day file name (`YYYY-MM-DD.json`).
+pub fn parse_day_file(file: &str) -> Option<NaiveDate> {
+    parse_date_stem(file.strip_suffix(".json")?)
+}
+
+/// Date of a day file or of its summary companion (`YYYY-MM-DD.summary.json`).
+pub fn parse_dated_file(file: &str) -> Option<NaiveDate> {
+    file.strip_suffix(".summary.json")
+        .and_then(parse_date_stem)
+        .or_else(|| parse_day_file(file))
+}
+
+fn parse_date_stem(date: &str) -> Option<NaiveDate> {
+    if date.len() != 10 {
+        return None;
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// Store `value` as a JSON object at `key`.
+#[instrument(skip(state, value))]
+pub async fn put_json(state: &AppState, key: &str, value: &impl serde::Serialize) -> Result<()> {
+    put_object(state, key, serde_json::to_vec(value)?, None).await
+}
+
+/// Write a JSON body holding `items` items to `key`, encrypting it when a key file is
+/// configured and applying the configured object options. Every object the server
+/// stores goes through here or [`put_object_if_unchanged`].
+#[instrument(skip(state, body), fields(bytes = body.len()))]
+pub async fn put_object(
+    state: &AppState,
+    key: &str,
+    body: Vec<u8>,
+    items: Option<usize>,
+) -> Result<()> {
+    put_request(state, key, body, items)?
+        .send()
+        .await
+        .map_err(Box::new)?;
+    Ok(())
+}
+
+/// Like [`put_object`], but only writes while the object is still as it was read: with
+/// the ETag `etag`, or absent when `etag` is `None`. Returns `false` when S3 rejects the
+/// write because the object was changed, created or deleted meanwhile.
+#[instrument(skip(state, body), fields(bytes = body.len()))]
+pub async fn put_object_if_unchanged(
+    state: &AppState,
+    key: &str,
+    body: Vec<u8>,
+    items: Option<usize>,
+    etag: Option<&str>,
+) -> Result<bool> {
+    let request = put_request(state, key, body, items)?;
+    let request = match etag {
+        Some(etag) => request.if_match(etag),
+        None => request.if_none_match("*"),
+    };
+    match request.send().await {
+        Ok(_) => Ok(true),
+        Err(err) if is_precondition_failure(&err) => {
+            debug!(%key, "object changed since it was read");
+            Ok(false)
+        }
+        Err(err) => Err(Error::from(Box::new(err))),
+    }
+}
+
+fn put_request(
+    state: &AppState,
+    key: &str,
+    body: Vec<u8>,
+    items: Option<usize>,
+) -> Result<PutObjectFluentBuilder> {
+    let (user, device) = object_owner(state, key).unzip();
+    let info = ObjectInfo {
+        user: user.flatten(),
+        device,
+        items,
+    };
+    let request = state.s3.put_object().bucket(&state.bucket).key(key);
+    Ok(match state.encryption.as_deref() {
+        Some(keyring) => {
+            let (body, envelope) = keyring.encrypt(key, &body)?;
+            state
+                .object_options
+                .apply_put(request, info, envelope.metadata())
+                .content_type("application/octet-stream")
+                .body(ByteStream::from(body))
+        }
+        None => state
+            .object_options
+            .apply_put(request, info, HashMap::new())
+            .content_type("application/json")
+            .body(ByteStream::from(body)),
+    })
+}
+
+// User and device a day file, summary or rollup key belongs to.
+fn object_owner<'a>(state: &AppState, key: &'a str) -> Option<(Option<&'a str>, &'a str)> {
+    let relative = key.strip_prefix(state.prefix.as_deref().unwrap_or_default())?;
+    let (dir, file) = relative.rsplit_once('/')?;
+    parse_dated_file(file)?;
+    let segments: Vec<&str> = dir.split('/').collect();
+    let owner = match segments[..] {
+        [ROLLUP_DIR, device] => (None, device),
+        [user, ROLLUP_DIR, device] => (Some(user), device),
+        [device] => (None, device),
+        [user, device] => (Some(user), device),
+        _ => return None,
+    };
+    let reserved = owner.0.is_some_and(|u| u.starts_with('_')) || owner.1.starts_with('_');
+    (!reserved).then_some(owner)
+}
+
+/// Key prefix holding all day files of one device.
+pub fn device_prefix(prefix: &Option<String>, user: Option<&str>, device_name: &str) -> String {
+    format!(
+        "{}{}/",
+        user_prefix(prefix, user),
+        sanitize_path_segment(device_name)
+    )
+}
+
+/// Items stored for a device on one day, including its rollup tier when rollups are
+/// enabled; empty if nothing was stored.
+#[instrument(skip(state))]
+pub async fn load_day(
+    state: &AppState,
+    user: Option<&str>,
+    device_name: &str,
+    date: NaiveDate,
+) -> Result<Vec<JsonValue>> {
+    let key = s3_key_for_device_date(&state.prefix, user, device_name, date);
+    let mut items = into_items(load_json(state, &key).await?);
+    if state.rollup.is_some() {
+        let key = rollup_key(&state.prefix, user, device_name, date);
+        items.append(&mut into_items(load_json(state, &key).await?));
+    }
+    Ok(items)
+}
+
+/// Items of a stored day or rollup tier file; a single non-array value is one item.
+pub fn into_items(stored: Option<JsonValue>) -> Vec<JsonValue> {
+    match stored {
+        Some(JsonValue::Array(a)) => a,
+        Some(other) => vec![other],
+        None => Vec::new(),
+    }
+}
+
+/// Items of each day file of a device over an inclusive range of days, fetched
+/// concurrently and yielded in date order; missing days yield no items.
+pub fn day_stream(
+    state: AppState,
+    user: Option<String>,
+    device_name: String,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> impl Stream<Item = Result<Vec<JsonValue>>> + Send + 'static {
+    let state = Arc::new(state);
+    futures_util::stream::iter(from.iter_days().take_while(move |d| *d <= to))
+        .map(move |date| {
+            let (state, user, device_name) = (state.clone(), user.clone(), device_name.clone());
+            async move { load_day(&state, user.as_deref(), &device_name, date).await }
+        })
+        .buffered(DAY_READ_CONCURRENCY)
+}
+
+/// Items stored for a device over an inclusive range of days; missing days are skipped.
+#[instrument(skip(state))]
+pub async fn load_day_range(
+    state: &AppState,
+    user: Option<&str>,
+    device_name: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<JsonValue>> {
+    let stream = day_stream(
+        state.clone(),
+        user.map(str::to_string),
+        device_name.to_string(),
+        from,
+        to,
+    );
+    let days: Vec<Vec<JsonValue>> = stream.try_collect().await?;
+    Ok(days.concat())
+}
+
+#[instrument(skip(state, new_json))]
+pub async fn save_or_merge_json(state: &AppState, key: &str, new_json: JsonValue) -> Result<()> {
+    // Try to fetch existing object
+    debug!(%key, "checking existing object");
+    let existing_json = load_json(state, key).await?;
 
     let merged = match existing_json {
         None => new_json,
         Some(old) => merge_json(old, new_json),
     };
 
-    let body = serde_json::to_vec_pretty(&merged)?;
-    let items_after = match &merged {
+    store_day(state, key, &merged).await
+}
+
+/// Write a day file, replacing any existing one, and refresh its summary.
+#[instrument(skip(state, day))]
+pub async fn store_day(state: &AppState, key: &str, day: &JsonValue) -> Result<()> {
+    let body = serde_json::to_vec_pretty(day)?;
+    let items_after = match day {
         JsonValue::Array(a) => a.len(),
         _ => 1,
     };
-    debug!(%key, items_after, bytes = body.len(), "writing merged JSON to S3");
-    state
-        .s3
-        .put_object()
-        .bucket(&state.bucket)
-        .key(key)
-        .content_type("application/json")
-        .body(ByteStream::from(body))
-        .send()
-        .await
-        .map_err(Box::new)?;
+    debug!(%key, items_after, bytes = body.len(), "writing day file to S3");
+    put_object(state, key, body, Some(items_after)).await?;
     debug!(%key, "put_object completed");
 
+    // The summary is derived data; a failed write is logged and rebuilt on the next merge.
+    if let Err(err) = save_summary(state, key, day).await {
+        warn!(error = ?err, %key, "failed to write day summary");
+    }
+
+    Ok(())
+}
+
+/// Write a day file only if it is unchanged since it was read with the ETag `etag`
+/// (see [`put_json_if_unchanged`]), and refresh its summary. Returns `false` when the
+/// file was changed or deleted meanwhile.
+#[instrument(skip(state, day))]
+pub async fn store_day_if_unchanged(
+    state: &AppState,
+    key: &str,
+    day: &JsonValue,
+    etag: Option<&str>,
+) -> Result<bool> {
+    if !put_json_if_unchanged(state, key, day, etag).await? {
+        return Ok(false);
+    }
+    if let Err(err) = save_summary(state, key, day).await {
+        warn!(error = ?err, %key, "failed to write day summary");
+    }
+    Ok(true)
+}
+
+/// Key of the summary companion of a day file (`<date>.summary.json`).
+pub fn summary_key(day_key: &str) -> String {
+    format!(
+        "{}.summary.json",
+        day_key.strip_suffix(".json").unwrap_or(day_key)
+    )
+}
+
+/// Summary of a day file, counting the aggregates of its rollup tier file when rollups
+/// are enabled.
+pub async fn summarize_day(
+    state: &AppState,
+    day_key: &str,
+    day: &JsonValue,
+) -> Result<summary::DaySummary> {
+    let rollup = day_key
+        .strip_prefix(state.prefix.as_deref().unwrap_or_default())
+        .and_then(parse_stored_day)
+        .filter(|_| state.rollup.is_some())
+        .map(|(user, device, date)| rollup_key(&state.prefix, user, device, date));
+    let Some(rollup_key) = rollup else {
+        return Ok(summary::summarize(day));
+    };
+    let mut items = into_items(Some(day.clone()));
+    items.extend(
+        into_items(load_json(state, &rollup_key).await?)
+            .into_iter()
+            .filter(|item| !rollup::is_record(item)),
+    );
+    Ok(summary::summarize(&JsonValue::Array(items)))
+}
+
+/// Rewrite the summary of a day file after its rollup tier file changed. The summary is
+/// derived data, so a failure is only logged.
+#[instrument(skip(state))]
+pub async fn refresh_summary(state: &AppState, day_key: &str) {
+    let res = match load_json(state, day_key).await {
+        Ok(Some(day)) => save_summary(state, day_key, &day).await,
+        Ok(None) => Ok(()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+        warn!(error = ?err, %day_key, "failed to refresh day summary");
+    }
+}
+
+/// Write the summary companion of the day file at `day_key`.
+#[instrument(skip(state, day))]
+pub async fn save_summary(state: &AppState, day_key: &str, day: &JsonValue) -> Result<()> {
+    let key = summary_key(day_key);
+    let summary = summarize_day(state, day_key, day).await?;
+    let items = summary.items;
+    put_object(state, &key, serde_json::to_vec(&summary)?, Some(items)).await?;
+    debug!(%key, "summary written");
     Ok(())
 }
 
+/// A JSON object with the ETag it was read at, or `None` if it does not exist. Pass the
+/// ETag to [`put_json_if_unchanged`] to write the object back.
+#[instrument(skip(state))]
+pub async fn load_json_versioned(
+    state: &AppState,
+    key: &str,
+) -> Result<Option<(JsonValue, String)>> {
+    match state
+        .object_options
+        .apply_get(state.s3.get_object().bucket(&state.bucket).key(key))
+        .send()
+        .await
+    {
+        Ok(obj) => {
+            let etag = obj.e_tag().unwrap_or_default().to_string();
+            let bytes = read_object(state, key, obj).await?;
+            Ok(Some((serde_json::from_slice(&bytes)?, etag)))
+        }
+        Err(err) if is_s3_not_found(&err) => Ok(None),
+        Err(err) => Err(Error::from(Box::new(err))),
+    }
+}
+
+/// Write `value` to `key` only if the object is still as it was read (see
+/// [`put_object_if_unchanged`]).
+#[instrument(skip(state, value))]
+pub async fn put_json_if_unchanged(
+    state: &AppState,
+    key: &str,
+    value: &JsonValue,
+    etag: Option<&str>,
+) -> Result<bool> {
+    let items = value.as_array().map_or(1, Vec::len);
+    put_object_if_unchanged(
+        state,
+        key,
+        serde_json::to_vec_pretty(value)?,
+        Some(items),
+        etag,
+    )
+    .await
+}
+
+// 412 when the ETag no longer matches or the object now exists, 409 when a concurrent
+// conditional write won, 404 when the object was deleted.
+fn is_precondition_failure<E>(err: &SdkError<E>) -> bool {
+    matches!(
+        err.raw_response().map(|r| r.status().as_u16()),
+        Some(404 | 409 | 412)
+    )
+}
+
+/// Prepare an incoming payload for merging: optional unit normalization, then
+/// conversion into the configured storage format.
+pub fn for_storage(state: &AppState, device_name: &str, mut payload: JsonValue) -> JsonValue {
+    if state.normalize_units {
+        normalize::normalize_payload(&mut payload);
+    }
+    match state.storage_format {
+        StorageFormat::Raw => payload,
+        StorageFormat::Omh => omh::convert_payload(device_name, payload),
+    }
+}
+
+/// Delete `keys` in batches of up to 1000, returning the keys S3 failed to delete.
+#[instrument(skip(state, keys), fields(keys = keys.len()))]
+pub async fn delete_objects(state: &AppState, keys: &[String]) -> Result<Vec<String>> {
+    let mut failed = Vec::new();
+    for batch in keys.chunks(1000) {
+        let objects = batch
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let out = state
+            .s3
+            .delete_objects()
+            .bucket(&state.bucket)
+            .delete(
+                Delete::builder()
+                    .set_objects(Some(objects))
+                    .quiet(true)
+                    .build()?,
+            )
+            .send()
+            .await
+            .map_err(Box::new)?;
+        for err in out.errors() {
+            error!(key = ?err.key(), code = ?err.code(), message = ?err.message(), "failed to delete object");
+            failed.extend(err.key().map(str::to_string));
+        }
+    }
+    debug!(
+        deleted = keys.len() - failed.len(),
+        failed = failed.len(),
+        "deleted objects"
+    );
+    Ok(failed)
+}
+
 #[instrument(skip(existing, incoming))]
 pub fn merge_json(existing: JsonValue, incoming: JsonValue) -> JsonValue {
     match (existing, incoming) {
@@ -112,6 +683,7 @@ pub fn is_s3_not_found(err: &SdkError<GetObjectError>) -> bool {
 // Background job processing
 #[derive(Debug)]
 pub struct IngestJob {
+    pub user: Option<String>,
     pub device_name: String,
     pub payload: JsonValue,
 }
@@ -119,10 +691,11 @@ pub struct IngestJob {
 #[instrument(skip(state, job), fields(device_name = %job.device_name))]
 pub async fn process_job(state: Arc<AppState>, job: IngestJob) {
     let today = Utc::now().date_naive();
-    let key = s3_key_for_device_date(&state.prefix, &job.device_name, today);
+    let key = s3_key_for_device_date(&state.prefix, job.user.as_deref(), &job.device_name, today);
     // Track jobs in-flight via a gauge-like up/down counter
     metrics::inc_jobs_inflight();
-    let res = save_or_merge_json(&state, &key, job.payload).await;
+    let payload = for_storage(&state, &job.device_name, job.payload);
+    let res = save_or_merge_json(&state, &key, payload).await;
     metrics::dec_jobs_inflight();
 
     match res {
@@ -135,7 +708,7 @@ pub async fn process_job(state: Arc<AppState>, job: IngestJob) {
     }
 }
 
-fn sanitize_path_segment(s: &str) -> String {
+pub fn sanitize_path_segment(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     for ch in s.chars() {
         match ch {
@@ -145,3 +718,9 @@ fn sanitize_path_segment(s: &str) -> String {
     }
     out
 }
+
+/// Whether a user or device name would land in the `_` namespace reserved for
+/// `_rollup`, `_audit` and other internal trees.
+pub fn is_reserved_segment(s: &str) -> bool {
+    sanitize_path_segment(s).starts_with('_')
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use tracing::debug;
+
+use crate::error::{Error, Result};
+
+// Prefix of the scope names honored in JWT claims, so scopes an identity provider
+// issues for other services (e.g. a generic `admin`) grant nothing here.
+const CLAIM_NAMESPACE: &str = "ahe:";
+
+/// A permission a credential may hold; each route group requires one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Send data: `/ingest`, `/import/apple`.
+    Ingest,
+    /// Read stored data back.
+    Read,
+    /// Administrative operations; implies every other scope.
+    Admin,
+}
+
+impl Scope {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "ingest" => Some(Scope::Ingest),
+            "read" => Some(Scope::Read),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        match self {
+            Scope::Ingest => 1,
+            Scope::Read => 2,
+            Scope::Admin => 4,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Ingest => "ingest",
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+/// The set of scopes held by one credential.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Scopes(u8);
+
+impl Scopes {
+    pub const INGEST: Scopes = Scopes(1);
+
+    /// Parse a comma-separated list such as `ingest,read`; `None` if any name is unknown.
+    pub fn parse_list(list: &str) -> Option<Self> {
+        Self::from_names(list.split(','))
+    }
+
+    /// Parse scope names, e.g. from a JWT `scope` claim; `None` if any name is unknown.
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut bits = 0;
+        for name in names {
+            bits |= Scope::parse(name)?.bit();
+        }
+        (bits != 0).then_some(Scopes(bits))
+    }
+
+    /// Scopes named in a token claim as `ahe:ingest`, `ahe:read` or `ahe:admin`,
+    /// ignoring every other name.
+    pub fn from_claim_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let bits = names
+            .into_iter()
+            .filter_map(|name| Scope::parse(name.strip_prefix(CLAIM_NAMESPACE)?))
+            .fold(0, |bits, scope| bits | scope.bit());
+        (bits != 0).then_some(Scopes(bits))
+    }
+
+    pub fn allows(self, scope: Scope) -> bool {
+        self.0 & (scope.bit() | Scope::Admin.bit()) != 0
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = [Scope::Ingest, Scope::Read, Scope::Admin]
+            .into_iter()
+            .filter(|s| self.0 & s.bit() != 0)
+            .map(Scope::name)
+            .collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// Scopes per name, e.g. per client certificate common name.
+#[derive(Debug, Default)]
+pub struct ScopeMap {
+    scopes: HashMap<String, Scopes>,
+}
+
+impl ScopeMap {
+    /// Load a file with one `name:scope,...` entry per line; the name may itself contain
+    /// `:`. Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut map = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = line
+                .rsplit_once(':')
+                .filter(|(name, _)| !name.is_empty())
+                .and_then(|(name, list)| Some((name, Scopes::parse_list(list)?)));
+            let Some((name, scopes)) = entry else {
+                return Err(Error::Config(format!(
+                    "{}:{}: expected name:scopes with scopes from ingest, read, admin",
+                    path.display(),
+                    idx + 1
+                )));
+            };
+            map.scopes.insert(name.to_string(), scopes);
+        }
+        debug!(path = %path.display(), entries = map.scopes.len(), "scopes file loaded");
+        Ok(map)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Scopes> {
+        self.scopes.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn parse_list_accepts_known_names() {
+        let scopes = Scopes::parse_list(" ingest , read").unwrap();
+        assert!(scopes.allows(Scope::Ingest) && scopes.allows(Scope::Read));
+        assert!(!scopes.allows(Scope::Admin));
+        assert_eq!(scopes.to_string(), "ingest,read");
+        assert_eq!(Scopes::parse_list("ingest"), Some(Scopes::INGEST));
+    }
+
+    #[test]
+    fn admin_implies_every_scope() {
+        let admin = Scopes::parse_list("admin").unwrap();
+        assert!(admin.allows(Scope::Ingest) && admin.allows(Scope::Read));
+    }
+
+    #[test]
+    fn parse_list_rejects_unknown_or_empty_lists() {
+        assert_eq!(Scopes::parse_list("read,owner"), None);
+        assert_eq!(Scopes::parse_list(""), None);
+        assert_eq!(Scopes::parse_list("read,"), None);
+    }
+
+    #[test]
+    fn claims_only_grant_namespaced_scopes() {
+        let scopes = Scopes::from_claim_names(["openid", "ahe:read", "admin"]).unwrap();
+        assert!(scopes.allows(Scope::Read));
+        assert!(!scopes.allows(Scope::Admin) && !scopes.allows(Scope::Ingest));
+        let admin = Scopes::from_claim_names(["ahe:admin"]).unwrap();
+        assert!(admin.allows(Scope::Admin));
+        assert_eq!(Scopes::from_claim_names(["admin", "read", "ingest"]), None);
+    }
+
+    #[test]
+    fn scope_map_names_may_contain_colons() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"# certs\nbackup:job:read\nphone:ingest\n")
+            .unwrap();
+        let map = ScopeMap::from_file(file.path()).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("backup:job"), Scopes::parse_list("read"));
+        assert_eq!(map.get("phone"), Some(Scopes::INGEST));
+        assert_eq!(map.get("watch"), None);
+
+        let mut bad = tempfile::NamedTempFile::new().unwrap();
+        bad.write_all(b"phone:owner\n").unwrap();
+        assert!(ScopeMap::from_file(bad.path()).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::debug;
+
+use crate::error::{Error, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shared secrets of clients that sign requests, keyed by client id.
+#[derive(Debug, Default)]
+pub struct SigningClients {
+    secrets: HashMap<String, Vec<u8>>,
+}
+
+impl SigningClients {
+    /// Load a clients file with one `client_id:secret` entry per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut store = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((client, secret)) if !client.is_empty() && !secret.is_empty() => {
+                    store
+                        .secrets
+                        .insert(client.to_string(), secret.as_bytes().to_vec());
+                }
+                _ => {
+                    return Err(Error::Config(format!(
+                        "{}:{}: expected client_id:secret",
+                        path.display(),
+                        idx + 1
+                    )));
+                }
+            }
+        }
+        debug!(path = %path.display(), clients = store.secrets.len(), "signing clients file loaded");
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Check `signature` (hex, optionally prefixed with `sha256=`) against the
+    /// HMAC-SHA256 of `"{timestamp}.{body}"` under the client's secret.
+    pub fn verify(&self, client: &str, timestamp: &str, body: &[u8], signature: &str) -> bool {
+        let Some(secret) = self.secrets.get(client) else {
+            return false;
+        };
+        let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+        let Ok(expected) = hex::decode(signature.trim()) else {
+            return false;
+        };
+        let Ok(mut mac) = HmacSha256::new_from_slice(secret) else {
+            return false;
+        };
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        // verify_slice compares in constant time
+        mac.verify_slice(&expected).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn load(text: &str) -> Result<SigningClients> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        SigningClients::from_file(file.path())
+    }
+
+    fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn verifies_signatures_over_timestamp_and_body() {
+        let clients = load("# clients\nphone:s3cr:et\n").unwrap();
+        assert_eq!(clients.len(), 1);
+        let signature = sign("s3cr:et", "1700000000", b"{}");
+        assert!(clients.verify("phone", "1700000000", b"{}", &signature));
+        assert!(clients.verify("phone", "1700000000", b"{}", &format!("sha256={signature}")));
+        assert!(!clients.verify("phone", "1700000001", b"{}", &signature));
+        assert!(!clients.verify("phone", "1700000000", b"[]", &signature));
+        assert!(!clients.verify("watch", "1700000000", b"{}", &signature));
+        assert!(!clients.verify("phone", "1700000000", b"{}", "not hex"));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(load("phone\n").is_err());
+        assert!(load("phone:\n").is_err());
+        assert!(load(":secret\n").is_err());
+    }
+}
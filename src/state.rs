@@ -1,50 +1,209 @@
 use aws_sdk_s3::Client as S3Client;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{Semaphore, mpsc};
 use tracing::debug;
 
+use crate::api_keys::ApiKeyStore;
+use crate::apple_export::ImportJobs;
 use crate::config::Config;
-use crate::config::normalize_prefix;
+use crate::config::{StorageFormat, normalize_prefix};
+use crate::encryption::Keyring;
+use crate::error::{Error, Result};
+use crate::jwt::JwtVerifier;
+use crate::lockout::AuthGuard;
+use crate::object_options::ObjectOptions;
+use crate::ratelimit::{Quotas, RateLimiter};
+use crate::retention::RetentionPolicy;
+use crate::rollup::RollupPolicy;
 use crate::s3::IngestJob;
+use crate::scopes::{ScopeMap, Scopes};
+use crate::signature::SigningClients;
+use crate::users::UserStore;
 
 #[derive(Clone)]
 pub struct AppState {
     pub s3: S3Client,
     pub bucket: String,
     pub prefix: Option<String>,
-    pub basic_auth: Option<String>, // stored as "user:pass"
+    pub users: Option<Arc<UserStore>>,
+    pub import_max_bytes: u64,
+    /// Permits for running Apple Health imports.
+    pub import_slots: Arc<Semaphore>,
+    pub import_jobs: Arc<ImportJobs>,
+    pub api_keys: Option<Arc<ApiKeyStore>>,
+    pub jwt: Option<Arc<JwtVerifier>>,
+    pub signing: Option<Arc<SigningClients>>,
+    pub signature_max_skew_secs: u64,
+    pub client_cert_auth: bool,
+    pub client_cert_scopes: Option<Arc<ScopeMap>>,
+    pub auth_guard: Option<Arc<AuthGuard>>,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub quotas: Option<Arc<Quotas>>,
+    pub retention: Option<Arc<RetentionPolicy>>,
+    pub rollup: Option<Arc<RollupPolicy>>,
+    pub encryption: Option<Arc<Keyring>>,
+    pub object_options: Arc<ObjectOptions>,
+    pub normalize_units: bool,
+    pub storage_format: StorageFormat,
     pub tx: mpsc::Sender<IngestJob>,
 }
 
+impl AppState {
+    /// Whether any credential type is configured, so data lives under user prefixes.
+    pub fn auth_configured(&self) -> bool {
+        self.users.is_some()
+            || self.api_keys.is_some()
+            || self.jwt.is_some()
+            || self.signing.is_some()
+            || self.client_cert_auth
+    }
+}
+
 pub struct WorkerHandles {
     #[allow(dead_code)]
     pub join_handles: Vec<tokio::task::JoinHandle<()>>,
 }
 
-pub fn build_state(config: &Config, s3: S3Client) -> (AppState, mpsc::Receiver<IngestJob>) {
+pub fn build_state(config: &Config, s3: S3Client) -> Result<(AppState, mpsc::Receiver<IngestJob>)> {
     let (tx, rx) = mpsc::channel::<IngestJob>(config.queue_cap);
-    let basic_auth = match (&config.basic_user, &config.basic_pass) {
-        (Some(u), Some(p)) => Some(format!("{}:{}", u, p)),
-        _ => None,
+    let mut users = match &config.users_file {
+        Some(path) => UserStore::from_file(path)?,
+        None => UserStore::default(),
+    };
+    if let (Some(u), Some(p)) = (&config.basic_user, &config.basic_pass) {
+        let scopes = Scopes::parse_list(&config.basic_scopes).ok_or_else(|| {
+            Error::Config(format!(
+                "invalid basic auth scopes {:?}; expected ingest, read, admin",
+                config.basic_scopes
+            ))
+        })?;
+        users.insert(u, p, scopes)?;
+    }
+    let users = (!users.is_empty()).then(|| Arc::new(users));
+    let api_keys = match &config.api_keys_file {
+        Some(path) => ApiKeyStore::from_file(path)?,
+        None => ApiKeyStore::default(),
+    };
+    let api_keys = (!api_keys.is_empty()).then(|| Arc::new(api_keys));
+    let signing = match &config.signing_clients_file {
+        Some(path) => SigningClients::from_file(path)?,
+        None => SigningClients::default(),
     };
+    let signing = (!signing.is_empty()).then(|| Arc::new(signing));
+    let client_cert_scopes = config
+        .tls_client_scopes_file
+        .as_deref()
+        .map(ScopeMap::from_file)
+        .transpose()?
+        .map(Arc::new);
+    let jwt = config.jwt_jwks.as_deref().map(|jwks| {
+        Arc::new(JwtVerifier::new(
+            jwks,
+            config.jwt_issuer.clone(),
+            config.jwt_audience.clone(),
+            config.jwt_user_claim.clone(),
+            Duration::from_secs(config.jwt_jwks_ttl_secs),
+        ))
+    });
+    let auth_guard = (config.auth_max_failures > 0).then(|| {
+        Arc::new(AuthGuard::new(
+            config.auth_max_failures,
+            Duration::from_secs(config.auth_lockout_secs),
+        ))
+    });
+    let rate_limiter = match config.rate_limit_per_sec {
+        Some(rate) if rate > 0.0 => Some(Arc::new(RateLimiter::new(rate, config.rate_limit_burst))),
+        Some(rate) => {
+            return Err(Error::Config(format!(
+                "rate limit must be positive, got {rate}"
+            )));
+        }
+        None => None,
+    };
+    let quotas =
+        (config.quota_daily_bytes.is_some() || config.quota_daily_items.is_some()).then(|| {
+            Arc::new(Quotas::new(
+                config.quota_daily_bytes,
+                config.quota_daily_items,
+            ))
+        });
+    let retention = config
+        .retention
+        .as_deref()
+        .map(RetentionPolicy::parse)
+        .transpose()
+        .map_err(Error::Config)?
+        .map(Arc::new);
+    let rollup = config
+        .rollup
+        .as_deref()
+        .map(|spec| RollupPolicy::parse(spec, config.rollup_after_days))
+        .transpose()
+        .map_err(Error::Config)?
+        .map(Arc::new);
+    let encryption = config
+        .encryption_key_file
+        .as_deref()
+        .map(Keyring::from_file)
+        .transpose()?
+        .map(Arc::new);
+    let object_options = Arc::new(ObjectOptions::from_config(config)?);
     debug!(
         bucket = %config.bucket,
         prefix = ?config.prefix,
         queue_cap = %config.queue_cap,
         workers = %config.workers,
-        basic_auth_enabled = %basic_auth.is_some(),
+        basic_auth_users = %users.as_ref().map_or(0, |u| u.len()),
+        import_max_bytes = %config.import_max_bytes,
+        import_concurrency = %config.import_concurrency,
+        api_keys = %api_keys.as_ref().map_or(0, |k| k.len()),
+        jwt_enabled = %jwt.is_some(),
+        signing_clients = %signing.as_ref().map_or(0, |c| c.len()),
+        client_cert_auth = %config.tls_client_ca.is_some(),
+        client_cert_scopes = %client_cert_scopes.as_ref().map_or(0, |m| m.len()),
+        auth_max_failures = %config.auth_max_failures,
+        rate_limit_per_sec = ?config.rate_limit_per_sec,
+        quota_daily_bytes = ?config.quota_daily_bytes,
+        quota_daily_items = ?config.quota_daily_items,
+        retention_rules = %retention.as_ref().map_or(0, |r| r.rules().len()),
+        rollup_metrics = %rollup.as_ref().map_or(0, |r| r.metrics().len()),
+        encryption_keys = %encryption.as_ref().map_or(0, |k| k.len()),
+        sse = ?config.sse,
+        storage_class = ?config.storage_class,
+        object_metadata = %config.object_metadata,
+        normalize_units = %config.normalize_units,
+        storage_format = ?config.storage_format,
         "AppState constructed"
     );
-    (
+    Ok((
         AppState {
             s3,
             bucket: config.bucket.clone(),
             prefix: config.prefix.clone().map(normalize_prefix),
-            basic_auth,
+            users,
+            import_max_bytes: config.import_max_bytes,
+            import_slots: Arc::new(Semaphore::new(config.import_concurrency.max(1))),
+            import_jobs: Arc::default(),
+            api_keys,
+            jwt,
+            signing,
+            signature_max_skew_secs: config.signature_max_skew_secs,
+            client_cert_auth: config.tls_client_ca.is_some(),
+            client_cert_scopes,
+            auth_guard,
+            rate_limiter,
+            quotas,
+            retention,
+            rollup,
+            encryption,
+            object_options,
+            normalize_units: config.normalize_units,
+            storage_format: config.storage_format,
             tx,
         },
         rx,
-    )
+    ))
 }
 
 pub fn spawn_workers(
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::aggregate::Stats;
+use crate::items;
+
+/// Small companion of a day file: per-metric statistics of its samples.
+#[derive(Debug, Serialize)]
+pub struct DaySummary {
+    /// Stored items in the day file, plus the aggregates of its rollup tier file when
+    /// rollups are on.
+    pub items: usize,
+    pub metrics: BTreeMap<String, MetricSummary>,
+    pub generated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricSummary {
+    pub unit: Option<String>,
+    #[serde(flatten)]
+    pub stats: Stats,
+    /// Earliest and latest sample start.
+    pub first: DateTime<FixedOffset>,
+    pub last: DateTime<FixedOffset>,
+}
+
+/// Summarize the contents of a day file.
+pub fn summarize(day: &JsonValue) -> DaySummary {
+    let stored: &[JsonValue] = match day {
+        JsonValue::Array(a) => a,
+        other => std::slice::from_ref(other),
+    };
+    let mut metrics: BTreeMap<String, MetricSummary> = BTreeMap::new();
+    for sample in stored.iter().flat_map(items::samples) {
+        let stats = Stats::of(&sample);
+        match metrics.get_mut(&sample.metric) {
+            Some(summary) => {
+                summary.stats.merge(&stats);
+                summary.first = summary.first.min(sample.start);
+                summary.last = summary.last.max(sample.start);
+            }
+            None => {
+                metrics.insert(
+                    sample.metric,
+                    MetricSummary {
+                        unit: sample.unit,
+                        stats,
+                        first: sample.start,
+                        last: sample.start,
+                    },
+                );
+            }
+        }
+    }
+    DaySummary {
+        items: stored.len(),
+        metrics,
+        generated_at: Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn summarizes_each_metric() {
+        let day = json!([
+            {"name": "step_count", "units": "count", "data": [
+                {"date": "2025-09-07 08:00:00 +0200", "qty": 100},
+                {"date": "2025-09-07 06:00:00 +0200", "qty": 20},
+                {"date": "2025-09-07 22:00:00 +0200", "qty": 300}
+            ]},
+            {"name": "heart_rate", "units": "count/min", "data": [
+                {"date": "2025-09-07 09:00:00 +0200", "Avg": 62}
+            ]}
+        ]);
+        let summary = summarize(&day);
+        assert_eq!(summary.items, 2);
+        assert_eq!(summary.metrics.len(), 2);
+
+        let steps = &summary.metrics["step_count"];
+        assert_eq!(steps.unit.as_deref(), Some("count"));
+        assert_eq!(steps.stats.count, 3);
+        assert_eq!(steps.stats.sum, 420.0);
+        assert_eq!(steps.stats.min, 20.0);
+        assert_eq!(steps.stats.max, 300.0);
+        assert_eq!(steps.first.to_rfc3339(), "2025-09-07T06:00:00+02:00");
+        assert_eq!(steps.last.to_rfc3339(), "2025-09-07T22:00:00+02:00");
+    }
+
+    #[test]
+    fn single_object_day_counts_as_one_item() {
+        let summary = summarize(&json!({"note": "no samples"}));
+        assert_eq!(summary.items, 1);
+        assert!(summary.metrics.is_empty());
+    }
+}
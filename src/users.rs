@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use once_cell::sync::Lazy;
+use subtle::ConstantTimeEq;
+use tracing::debug;
+
+use crate::config::HashAlgorithm;
+use crate::error::{Error, Result};
+use crate::scopes::Scopes;
+
+/// A configured password: plaintext, or an argon2id/bcrypt hash in PHC/MCF form.
+#[derive(Debug)]
+enum Credential {
+    Plain(String),
+    Argon2(String),
+    Bcrypt(String),
+}
+
+fn is_argon2(secret: &str) -> bool {
+    secret.starts_with("$argon2")
+}
+
+fn is_bcrypt(secret: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|p| secret.starts_with(p))
+}
+
+fn is_hash(secret: &str) -> bool {
+    is_argon2(secret) || is_bcrypt(secret)
+}
+
+impl Credential {
+    fn parse(secret: &str) -> Result<Self> {
+        if is_argon2(secret) {
+            PasswordHash::new(secret).map_err(|e| Error::PasswordHash(e.to_string()))?;
+            return Ok(Credential::Argon2(secret.to_string()));
+        }
+        if is_bcrypt(secret) {
+            return Ok(Credential::Bcrypt(secret.to_string()));
+        }
+        Ok(Credential::Plain(secret.to_string()))
+    }
+
+    fn is_hashed(&self) -> bool {
+        !matches!(self, Credential::Plain(_))
+    }
+
+    // All comparisons run in constant time with respect to the password content.
+    fn verify(&self, pass: &str) -> bool {
+        match self {
+            Credential::Plain(expected) => expected.as_bytes().ct_eq(pass.as_bytes()).into(),
+            Credential::Argon2(hash) => PasswordHash::new(hash).is_ok_and(|parsed| {
+                Argon2::default()
+                    .verify_password(pass.as_bytes(), &parsed)
+                    .is_ok()
+            }),
+            Credential::Bcrypt(hash) => bcrypt::verify(pass, hash).unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    credential: Credential,
+    scopes: Scopes,
+}
+
+/// Credentials accepted by basic auth, keyed by username.
+#[derive(Debug, Default)]
+pub struct UserStore {
+    users: HashMap<String, Entry>,
+    /// Whether any password is hashed, so unknown users must cost a hash verification.
+    hashed: bool,
+}
+
+// Verified in place of an unknown user's password, so a failed login takes as long
+// whether or not the username exists.
+static DUMMY_CREDENTIAL: Lazy<Credential> = Lazy::new(|| {
+    let hash = hash_password("ahe-unknown-user", HashAlgorithm::Argon2id)
+        .expect("hashing the dummy password");
+    Credential::Argon2(hash)
+});
+
+impl UserStore {
+    /// Load a users file with one `username:password` or `username:hash:scopes` entry
+    /// per line, where the hash is argon2id or bcrypt and scopes is a comma-separated
+    /// list (default: `ingest`). Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut store = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((user, rest)) if !user.is_empty() => {
+                    // Hashes never contain ':', so a scopes field is only recognized after
+                    // one; a plaintext password is everything after the username.
+                    let (pass, scopes) = match rest.split_once(':') {
+                        Some((hash, list)) if is_hash(hash) => {
+                            let scopes = Scopes::parse_list(list).ok_or_else(|| {
+                                Error::Config(format!(
+                                    "{}:{}: scopes must be a list of ingest, read, admin",
+                                    path.display(),
+                                    idx + 1
+                                ))
+                            })?;
+                            (hash, scopes)
+                        }
+                        _ => (rest, Scopes::INGEST),
+                    };
+                    store.insert(user, pass, scopes)?
+                }
+                _ => {
+                    return Err(Error::Config(format!(
+                        "{}:{}: expected username:password",
+                        path.display(),
+                        idx + 1
+                    )));
+                }
+            }
+        }
+        debug!(path = %path.display(), users = store.users.len(), "users file loaded");
+        Ok(store)
+    }
+
+    pub fn insert(&mut self, user: &str, pass: &str, scopes: Scopes) -> Result<()> {
+        let credential = Credential::parse(pass)?;
+        self.hashed |= credential.is_hashed();
+        self.users
+            .insert(user.to_string(), Entry { credential, scopes });
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// The user's scopes if `pass` is correct.
+    pub fn verify(&self, user: &str, pass: &str) -> Option<Scopes> {
+        match self.users.get(user) {
+            Some(entry) => entry.credential.verify(pass).then_some(entry.scopes),
+            None => {
+                if self.hashed {
+                    DUMMY_CREDENTIAL.verify(pass);
+                }
+                None
+            }
+        }
+    }
+
+    /// Like [`UserStore::verify`], moving hash verification off the async runtime.
+    pub async fn verify_async(self: &Arc<Self>, user: &str, pass: &str) -> Option<Scopes> {
+        let hashed = match self.users.get(user) {
+            Some(entry) => entry.credential.is_hashed(),
+            None => self.hashed,
+        };
+        if !hashed {
+            return self.verify(user, pass);
+        }
+        let store = self.clone();
+        let (user, pass) = (user.to_string(), pass.to_string());
+        tokio::task::spawn_blocking(move || store.verify(&user, &pass))
+            .await
+            .ok()
+            .flatten()
+    }
+}
+
+/// Hash a password for use in `AHE_BASIC_PASS` or a users file.
+pub fn hash_password(password: &str, algorithm: HashAlgorithm) -> Result<String> {
+    match algorithm {
+        HashAlgorithm::Argon2id => {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| Error::PasswordHash(e.to_string()))
+        }
+        HashAlgorithm::Bcrypt => bcrypt::hash(password, bcrypt::DEFAULT_COST)
+            .map_err(|e| Error::PasswordHash(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::scopes::Scope;
+
+    fn load(text: &str) -> Result<UserStore> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        UserStore::from_file(file.path())
+    }
+
+    #[test]
+    fn plaintext_passwords_keep_colons_and_get_ingest() {
+        let store = load("# users\n\nalice:pa:ss:read\n").unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.verify("alice", "pa:ss:read"), Some(Scopes::INGEST));
+        assert_eq!(store.verify("alice", "pa"), None);
+    }
+
+    #[test]
+    fn hashed_passwords_take_a_scopes_field() {
+        let argon = hash_password("secret", HashAlgorithm::Argon2id).unwrap();
+        let bcrypt = bcrypt::hash("other", 4).unwrap();
+        let store = load(&format!("alice:{argon}:read,admin\nbob:{bcrypt}\n")).unwrap();
+        let alice = store.verify("alice", "secret").unwrap();
+        assert!(alice.allows(Scope::Admin));
+        assert_eq!(store.verify("bob", "other"), Some(Scopes::INGEST));
+        assert_eq!(store.verify("bob", "secret"), None);
+        assert_eq!(store.verify("carol", "secret"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let argon = hash_password("secret", HashAlgorithm::Argon2id).unwrap();
+        assert!(load(&format!("alice:{argon}:owner\n")).is_err());
+        assert!(load("alice\n").is_err());
+        assert!(load(":secret\n").is_err());
+    }
+}